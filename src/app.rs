@@ -1,15 +1,29 @@
-use crate::{install, ui};
+use crate::{
+    install::{self, Plan},
+    manifest::Manifest,
+    registry::Install,
+    source::Release,
+    ui,
+};
 pub use color_eyre::{
     Result,
     eyre::{self, eyre},
 };
 use crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind};
 use ratatui::{DefaultTerminal, prelude::*, widgets::*};
-use std::{sync::mpsc, thread};
+use std::{path::PathBuf, sync::mpsc, thread};
 
 pub enum Event {
     Key(KeyEvent),
-    Progress(f64),
+    Progress(Progress),
+    // Releases to pick from, and the component they belong to
+    Releases(Vec<Release>, String),
+    // Side by side installs of a component, and the folder of the active one
+    Installs(String, Vec<Install>, Option<PathBuf>),
+    // What an install would do, to be confirmed
+    Plan(Plan),
+    // A change which failed and was undone, shown before going back to the menu
+    Failed(eyre::Report),
     Error(eyre::Report),
 }
 
@@ -21,37 +35,116 @@ pub fn send_key_event(tx: mpsc::Sender<Event>) {
     }
 }
 
-pub fn send_progress_event(ratio: f64, tx: &mpsc::Sender<Event>) {
-    tx.send(Event::Progress(ratio)).unwrap()
+#[derive(Default, Clone, Debug)]
+pub struct Progress {
+    // What is being done, e.g. "Downloading" or "Extracting"
+    pub phase: &'static str,
+    pub name: String,
+    pub done: u64,
+    pub total: u64,
+    // Bytes per second
+    pub speed: f64,
+}
+
+impl Progress {
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.done as f64 / self.total as f64
+    }
+}
+
+pub fn send_progress_event(progress: Progress, tx: &mpsc::Sender<Event>) {
+    tx.send(Event::Progress(progress)).unwrap()
+}
+
+pub fn send_releases_event(releases: Vec<Release>, component: String, tx: &mpsc::Sender<Event>) {
+    tx.send(Event::Releases(releases, component)).unwrap()
+}
+
+pub fn send_installs_event(
+    component: String,
+    installs: Vec<Install>,
+    active: Option<PathBuf>,
+    tx: &mpsc::Sender<Event>,
+) {
+    tx.send(Event::Installs(component, installs, active))
+        .unwrap()
+}
+
+pub fn send_plan_event(plan: Plan, tx: &mpsc::Sender<Event>) {
+    tx.send(Event::Plan(plan)).unwrap()
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Screen {
+    #[default]
+    Menu,
+    Releases,
+    Installs,
+    Confirm,
+}
+
+// What the confirm screen is asking about
+pub enum Pending {
+    Install(Plan),
+    // Component id
+    Uninstall(String),
+    // Component id and the install's folder
+    DeleteInstall(String, PathBuf),
 }
 
 pub struct App {
     pub evtx: mpsc::Sender<Event>,
     pub evrx: mpsc::Receiver<Event>,
     pub list: ui::FnList<'static, mpsc::Sender<Event>>,
+    pub rels: ui::ReleaseList<'static>,
+    pub insts: ui::InstallList<'static>,
+    pub confirm: ui::Confirm<'static>,
+    pub pending: Option<Pending>,
+    // Where the confirm screen goes back to
+    pub confirm_back: Screen,
+    pub screen: Screen,
     pub pbar: ui::ProgressBar,
     pub exit: bool,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self> {
         let (evtx, evrx) = mpsc::channel::<Event>();
 
-        let items = vec![
-            ui::FnItem::new(install::obs, "Install OBS (Open Broadcast Software)"),
-            ui::FnItem::new(install::khs, "Install Kilohearts Essentials"),
-            #[cfg(any(target_os = "windows", target_os = "linux"))]
-            ui::FnItem::new(install::rea, "Install ReaPlugs"),
-            #[cfg(target_os = "macos")]
-            ui::FnItem::new(install::eab, "Install BlackHole Driver"),
-        ];
+        // One item per component in the manifest, with a version picker if it has releases and
+        // a list of installed versions if they're kept side by side
+        let items = Manifest::load()?
+            .visible()
+            .map(|c| {
+                let desc = format!("Install {}", c.name);
+                let mut item = ui::FnItem::new(|tx, id| install::plan(tx, id, None), &c.id, &desc);
+                if c.source.is_some() {
+                    item = item.with_versions(install::releases);
+                }
+                if c.side_by_side.is_some() {
+                    item = item.with_installs(install::installs);
+                }
+                item
+            })
+            .collect();
 
         let state = ListState::default().with_selected(Some(0));
         let header = Line::from(" OBS Install Manager ".bold());
         let footer = Line::from(
-            [" Up <↑>", "Down <↓>", "Accept <Enter>", "Exit <Esc> "]
-                .join(" - ")
-                .bold(),
+            [
+                " Up <↑>",
+                "Down <↓>",
+                "Accept <Enter>",
+                "Versions <V>",
+                "Installed <I>",
+                "Uninstall <U>",
+                "Exit <Esc> ",
+            ]
+            .join(" - ")
+            .bold(),
         );
 
         let list = ui::FnList {
@@ -61,18 +154,49 @@ impl App {
             footer,
         };
 
-        let pbar = ui::ProgressBar {
-            title: " Downloading ",
+        let rels = ui::ReleaseList {
+            header: Line::from(" Releases ".bold()),
+            footer: Line::from(
+                [" Up <↑>", "Down <↓>", "Install <Enter>", "Back <Esc> "]
+                    .join(" - ")
+                    .bold(),
+            ),
+            ..Default::default()
+        };
+
+        let insts = ui::InstallList {
+            header: Line::from(" Installed versions ".bold()),
+            footer: Line::from(
+                [
+                    " Up <↑>",
+                    "Down <↓>",
+                    "Make active <Enter>",
+                    "Delete <D>",
+                    "Back <Esc> ",
+                ]
+                .join(" - ")
+                .bold(),
+            ),
             ..Default::default()
         };
 
-        Self {
+        let confirm = ui::Confirm::default();
+
+        let pbar = ui::ProgressBar::default();
+
+        Ok(Self {
             evtx,
             evrx,
             list,
+            rels,
+            insts,
+            confirm,
+            pending: None,
+            confirm_back: Screen::Menu,
+            screen: Screen::Menu,
             pbar,
             exit: false,
-        }
+        })
     }
 
     pub fn run(&mut self, mut term: DefaultTerminal) -> Result<()> {
@@ -86,7 +210,11 @@ impl App {
         while !self.exit {
             match self.evrx.recv()? {
                 Event::Key(k) => self.handle_key_event(k),
-                Event::Progress(p) => self.pbar.set_ratio(p),
+                Event::Progress(p) => self.pbar.set_progress(p),
+                Event::Releases(r, c) => self.show_releases(r, c),
+                Event::Installs(c, i, a) => self.show_installs(c, i, a),
+                Event::Plan(p) => self.show_plan(p),
+                Event::Failed(e) => self.show_failed(e),
                 Event::Error(e) => return Err(e),
             }
 
@@ -101,19 +229,54 @@ impl App {
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
-        if self.pbar.ratio != 0.0 {
+        if self.pbar.is_active() {
             return;
         }
 
-        if KeyEventKind::Press == key_event.kind {
-            match key_event.code {
+        if KeyEventKind::Press != key_event.kind {
+            return;
+        }
+
+        match self.screen {
+            Screen::Menu => match key_event.code {
                 KeyCode::Up => self.list.state.select_previous(),
                 KeyCode::Down => self.list.state.select_next(),
                 KeyCode::Enter => self.select_accept(),
+                KeyCode::Char('v') => self.select_versions(),
+                KeyCode::Char('i') => self.select_installs(),
+                KeyCode::Char('u') => self.select_uninstall(),
                 KeyCode::Esc => self.exit(),
                 _ => (),
-            }
-        };
+            },
+            Screen::Releases => match key_event.code {
+                KeyCode::Up => self.rels.state.select_previous(),
+                KeyCode::Down => self.rels.state.select_next(),
+                KeyCode::Enter => self.release_accept(),
+                KeyCode::Esc => self.screen = Screen::Menu,
+                _ => (),
+            },
+            Screen::Installs => match key_event.code {
+                KeyCode::Up => self.insts.state.select_previous(),
+                KeyCode::Down => self.insts.state.select_next(),
+                KeyCode::Enter => self.install_activate(),
+                KeyCode::Char('d') => self.install_delete(),
+                KeyCode::Esc => self.screen = Screen::Menu,
+                _ => (),
+            },
+            Screen::Confirm => match key_event.code {
+                KeyCode::Up => self.confirm.scroll_up(),
+                KeyCode::Down => self.confirm.scroll_down(),
+                KeyCode::Enter => self.confirm_accept(false),
+                KeyCode::Char('s') if matches!(self.pending, Some(Pending::Uninstall(_))) => {
+                    self.confirm_accept(true)
+                }
+                KeyCode::Esc => {
+                    self.pending = None;
+                    self.screen = self.confirm_back;
+                }
+                _ => (),
+            },
+        }
     }
 
     fn select_accept(&mut self) {
@@ -122,7 +285,200 @@ impl App {
             let item = self.list.items[selected].clone();
 
             thread::spawn(move || {
-                if let Err(e) = (item.op)(evtx.clone()) {
+                if let Err(e) = (item.op)(evtx.clone(), &item.arg) {
+                    let _ = evtx.send(Event::Error(e));
+                }
+            });
+        }
+    }
+
+    fn select_versions(&mut self) {
+        if let Some(selected) = self.list.state.selected()
+            && let Some(versions) = self.list.items[selected].versions
+        {
+            let evtx = self.evtx.clone();
+            let arg = self.list.items[selected].arg.clone();
+
+            thread::spawn(move || {
+                if let Err(e) = versions(evtx.clone(), &arg) {
+                    let _ = evtx.send(Event::Error(e));
+                }
+            });
+        }
+    }
+
+    fn select_installs(&mut self) {
+        if let Some(selected) = self.list.state.selected()
+            && let Some(installs) = self.list.items[selected].installs
+        {
+            let evtx = self.evtx.clone();
+            let arg = self.list.items[selected].arg.clone();
+
+            thread::spawn(move || {
+                if let Err(e) = installs(evtx.clone(), &arg) {
+                    let _ = evtx.send(Event::Error(e));
+                }
+            });
+        }
+    }
+
+    fn select_uninstall(&mut self) {
+        if let Some(selected) = self.list.state.selected() {
+            let item = &self.list.items[selected];
+            let name = item.desc.trim_start_matches("Install ");
+
+            self.show_confirm(
+                " Uninstall ",
+                &[" Yes <Enter>", "Also shared data <S>", "Cancel <Esc> "],
+                vec![
+                    format!("Remove {} and everything it installed?", name),
+                    "Shared data such as the OBS config is kept unless you press S.".into(),
+                ],
+                Some(Pending::Uninstall(item.arg.clone())),
+            );
+        }
+    }
+
+    fn show_confirm(
+        &mut self,
+        header: &str,
+        footer: &[&str],
+        lines: Vec<String>,
+        pending: Option<Pending>,
+    ) {
+        self.confirm.header = Line::from(header.to_string().bold());
+        self.confirm.footer = Line::from(footer.join(" - ").bold());
+        self.confirm.lines = lines;
+        self.confirm.scroll = 0;
+        self.pending = pending;
+        self.confirm_back = self.screen;
+        self.screen = Screen::Confirm;
+    }
+
+    // `shared` also removes data shared between installs
+    fn confirm_accept(&mut self, shared: bool) {
+        let evtx = self.evtx.clone();
+        self.screen = self.confirm_back;
+
+        match self.pending.take() {
+            Some(Pending::Install(plan)) => {
+                thread::spawn(move || {
+                    if let Err(e) = install::run_plan(evtx.clone(), &plan) {
+                        let _ = evtx.send(Event::Failed(e));
+                    }
+                });
+                self.screen = Screen::Menu;
+            }
+            Some(Pending::Uninstall(component)) => {
+                thread::spawn(move || {
+                    if let Err(e) = install::uninstall(&component, None, shared) {
+                        let _ = evtx.send(Event::Error(e));
+                    }
+                });
+            }
+            Some(Pending::DeleteInstall(component, path)) => {
+                thread::spawn(move || {
+                    let result = install::delete_install(&component, &path)
+                        .and_then(|_| install::installs(evtx.clone(), &component));
+                    if let Err(e) = result {
+                        let _ = evtx.send(Event::Error(e));
+                    }
+                });
+            }
+            None => (),
+        }
+    }
+
+    // The error and each of its causes on a line of their own
+    fn show_failed(&mut self, e: eyre::Report) {
+        let lines = e.chain().map(|cause| cause.to_string()).collect();
+        self.show_confirm(" Failed ", &[" Back <Enter/Esc> "], lines, None);
+        self.confirm_back = Screen::Menu;
+    }
+
+    fn show_plan(&mut self, plan: Plan) {
+        self.show_confirm(
+            &format!(" Install {} ", plan.name),
+            &[" Install <Enter>", "Scroll <↑/↓>", "Cancel <Esc> "],
+            plan.actions.clone(),
+            Some(Pending::Install(plan)),
+        );
+    }
+
+    fn show_installs(
+        &mut self,
+        component: String,
+        installs: Vec<Install>,
+        active: Option<PathBuf>,
+    ) {
+        self.insts.set_installs(installs, active, component);
+        self.screen = Screen::Installs;
+    }
+
+    fn install_activate(&mut self) {
+        if let (Some(install), Some(component)) = (self.insts.selected(), &self.insts.component)
+            && let Some(path) = install.path.clone()
+        {
+            let evtx = self.evtx.clone();
+            let component = component.clone();
+
+            thread::spawn(move || {
+                if let Err(e) = install::activate(evtx.clone(), &component, &path) {
+                    let _ = evtx.send(Event::Failed(e));
+                } else if let Err(e) = install::installs(evtx.clone(), &component) {
+                    let _ = evtx.send(Event::Error(e));
+                }
+            });
+        }
+    }
+
+    fn install_delete(&mut self) {
+        let (Some(install), Some(component)) = (self.insts.selected(), &self.insts.component)
+        else {
+            return;
+        };
+        let Some(path) = install.path.clone() else {
+            return;
+        };
+
+        if self.insts.is_active(install) {
+            self.show_confirm(
+                " Delete ",
+                &[" Back <Enter/Esc> "],
+                vec![
+                    format!("{} is the active version.", install.version),
+                    "Make another version active before deleting it.".into(),
+                ],
+                None,
+            );
+            return;
+        }
+
+        let pending = Pending::DeleteInstall(component.clone(), path.clone());
+        self.show_confirm(
+            " Delete ",
+            &[" Yes <Enter>", "Cancel <Esc> "],
+            vec![
+                format!("Delete version {}?", install.version),
+                format!("This removes {} and its plugins.", path.display()),
+            ],
+            Some(pending),
+        );
+    }
+
+    fn show_releases(&mut self, releases: Vec<Release>, component: String) {
+        self.rels.set_releases(releases, component);
+        self.screen = Screen::Releases;
+    }
+
+    fn release_accept(&mut self) {
+        if let (Some(release), Some(component)) = (self.rels.selected(), &self.rels.component) {
+            let evtx = self.evtx.clone();
+            let version = release.tag.clone();
+            let component = component.clone();
+
+            thread::spawn(move || {
+                if let Err(e) = install::plan(evtx.clone(), &component, Some(version)) {
                     let _ = evtx.send(Event::Error(e));
                 }
             });
@@ -139,18 +495,37 @@ impl Widget for &mut App {
     where
         Self: Sized,
     {
-        let width = self.list.width(area);
+        let (width, height) = match self.screen {
+            Screen::Menu => (self.list.width(area), self.list.height(area)),
+            // Leave room for the progress bar below the release list
+            Screen::Releases => (
+                self.rels.width(area),
+                self.rels.height(area).min(area.height.saturating_sub(3)),
+            ),
+            Screen::Installs => (self.insts.width(area), self.insts.height(area)),
+            Screen::Confirm => (self.confirm.width(area), self.confirm.height(area)),
+        };
+
+        let width = match self.pbar.is_active() {
+            true => width.max(self.pbar.width(area)),
+            false => width,
+        };
+
         let layout = Layout::horizontal([Constraint::Length(width)]).flex(layout::Flex::Center);
         let [cell] = layout.areas(area);
 
-        let height = self.list.height(area);
         let layout = Layout::vertical([Constraint::Length(height), Constraint::Length(3)])
             .flex(layout::Flex::Center);
         let [top, btm] = layout.areas(cell);
 
-        self.list.render(top, buf);
+        match self.screen {
+            Screen::Menu => self.list.render(top, buf),
+            Screen::Releases => self.rels.render(top, buf),
+            Screen::Installs => self.insts.render(top, buf),
+            Screen::Confirm => self.confirm.render(top, buf),
+        }
 
-        if self.pbar.ratio != 0.0 {
+        if self.pbar.is_active() {
             self.pbar.render(btm, buf);
         }
     }
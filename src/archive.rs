@@ -0,0 +1,987 @@
+use crate::{app::Event, file::Reporter};
+use color_eyre::{Result, eyre::eyre};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    sync::mpsc,
+};
+
+// Members a Debian package may keep its files in
+const DEB_DATA_MEMBERS: &[&str] = &["data.tar", "data.tar.gz", "data.tar.xz", "data.tar.zst"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Deb,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl Format {
+    // Sniffed from the file's leading bytes, falling back to its extension
+    pub fn detect(path: &Path) -> Result<Self> {
+        let mut magic = Vec::with_capacity(262);
+        fs::File::open(path)?.take(262).read_to_end(&mut magic)?;
+
+        let format = if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if magic.starts_with(b"!<arch>\n") {
+            Some(Self::Deb)
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::TarXz)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::TarZst)
+        } else if magic.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            None
+        };
+
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        let by_ext = |exts: &[&str]| exts.iter().any(|e| name.ends_with(e));
+
+        format
+            .or_else(|| {
+                [
+                    (Self::Zip, &[".zip"][..]),
+                    (Self::Deb, &[".deb"]),
+                    (Self::TarGz, &[".tar.gz", ".tgz"]),
+                    (Self::TarXz, &[".tar.xz", ".txz"]),
+                    (Self::TarZst, &[".tar.zst", ".tzst"]),
+                    (Self::Tar, &[".tar"]),
+                ]
+                .into_iter()
+                .find_map(|(format, exts)| by_ext(exts).then_some(format))
+            })
+            .ok_or_else(|| eyre!("Unsupported archive format: {}", path.display()))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExtractOptions {
+    // Restore unix permission bits and symlinks stored in the archive, on unix only
+    pub unix_metadata: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            unix_metadata: true,
+        }
+    }
+}
+
+// Extracts any supported archive into `extract_dir`, rejecting entries which would land
+// outside it before anything is written. Returns the files and folders which didn't exist
+// before, parents first.
+pub fn extract<P: AsRef<Path>>(
+    file_path: P,
+    extract_dir: P,
+    options: ExtractOptions,
+    progress_tx: &mpsc::Sender<Event>,
+) -> Result<Vec<PathBuf>> {
+    match Format::detect(file_path.as_ref())? {
+        Format::Zip => extract_zip(file_path, extract_dir, options, progress_tx),
+        Format::Deb => extract_deb(
+            file_path.as_ref(),
+            extract_dir.as_ref(),
+            options,
+            progress_tx,
+        ),
+        format => extract_tar(
+            file_path.as_ref(),
+            extract_dir.as_ref(),
+            format,
+            options,
+            progress_tx,
+        ),
+    }
+}
+
+#[cfg_attr(not(unix), allow(unused_variables, unused_mut))]
+fn extract_zip<P: AsRef<Path>>(
+    file_path: P,
+    extract_dir: P,
+    options: ExtractOptions,
+    progress_tx: &mpsc::Sender<Event>,
+) -> Result<Vec<PathBuf>> {
+    let name = file_path
+        .as_ref()
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(fs::File::open(&file_path)?))?;
+
+    // Check every entry up front so a bad archive leaves nothing half extracted
+    let mut total = 0;
+    let mut entry_paths = Vec::with_capacity(archive.len());
+    let mut links = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        // Reject absolute paths and `..` components which would write outside extract_dir
+        let entry_path = file.enclosed_name().ok_or_else(|| {
+            eyre!(
+                "Archive entry '{}' in {} escapes the extraction folder.",
+                file.name(),
+                name
+            )
+        })?;
+
+        if file.is_symlink() {
+            let mut target = String::new();
+            io::Read::read_to_string(&mut file, &mut target)?;
+            links.push((entry_path.clone(), PathBuf::from(target)));
+        }
+        total += file.size();
+        entry_paths.push(entry_path);
+    }
+
+    let extract_dir = extract_dir.as_ref();
+    check_not_under_links(&entry_paths, &links, &name)?;
+    check_links_enclosed(extract_dir, &links, &name)?;
+
+    // Created once every other entry is written, so nothing is written through them. Where they
+    // can't be restored they're written as files holding the target instead.
+    let restore_links = cfg!(unix) && options.unix_metadata;
+    let mut reporter = Reporter::new(progress_tx, "Extracting", &name, total);
+    let mut created = Vec::new();
+
+    for (i, entry_path) in entry_paths.into_iter().enumerate() {
+        let mut file = archive.by_index(i)?;
+        let extract_path = extract_dir.join(&entry_path);
+        check_no_links_on_disk(extract_dir, &entry_path, &name)?;
+
+        if file.is_dir() {
+            create_dir_all(&extract_path, extract_dir, &mut created)?;
+            continue;
+        }
+
+        if let Some(parent) = extract_path.parent() {
+            create_dir_all(parent, extract_dir, &mut created)?;
+        }
+
+        if restore_links && file.is_symlink() {
+            reporter.add(file.size());
+            continue;
+        }
+
+        replace_link(&extract_path, &mut created)?;
+        reporter.add(io::copy(&mut file, &mut fs::File::create(&extract_path)?)?);
+
+        // Permission bits only, setuid and friends are never restored
+        #[cfg(unix)]
+        if options.unix_metadata
+            && let Some(mode) = file.unix_mode()
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&extract_path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+
+    if restore_links {
+        create_links(extract_dir, links, &name, &mut created)?;
+    }
+    Ok(created)
+}
+
+// Debian packages are ar archives keeping the installed files in a `data.tar.*` member, which
+// is copied out next to the package and extracted like any other tar archive
+fn extract_deb(
+    file_path: &Path,
+    extract_dir: &Path,
+    options: ExtractOptions,
+    progress_tx: &mpsc::Sender<Event>,
+) -> Result<Vec<PathBuf>> {
+    let mut package = ar::Archive::new(io::BufReader::new(fs::File::open(file_path)?));
+
+    while let Some(member) = package.next_entry() {
+        let mut member = member?;
+        let id = String::from_utf8_lossy(member.header().identifier()).into_owned();
+
+        if !DEB_DATA_MEMBERS.contains(&id.as_str()) {
+            continue;
+        }
+
+        // foo.deb -> foo.data.tar.xz
+        let data_path = file_path.with_extension(&id);
+        io::copy(&mut member, &mut fs::File::create(&data_path)?)?;
+
+        let result = Format::detect(&data_path)
+            .and_then(|format| extract_tar(&data_path, extract_dir, format, options, progress_tx));
+        fs::remove_file(&data_path)?;
+        return result;
+    }
+
+    Err(eyre!(
+        "{} has no data archive, is it a Debian package?",
+        file_path.display()
+    ))
+}
+
+// Counts compressed bytes as they are read, tar archives having no up front size
+struct ProgressReader<'a, R> {
+    inner: R,
+    reporter: Option<Reporter<'a>>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(reporter) = &mut self.reporter {
+            reporter.add(n as u64);
+        }
+        Ok(n)
+    }
+}
+
+fn tar_archive<'a>(
+    path: &Path,
+    format: Format,
+    reporter: Option<Reporter<'a>>,
+) -> Result<tar::Archive<Box<dyn Read + 'a>>> {
+    let reader = ProgressReader {
+        inner: io::BufReader::new(fs::File::open(path)?),
+        reporter,
+    };
+
+    let reader: Box<dyn Read + 'a> = match format {
+        Format::TarGz => Box::new(flate2::read::GzDecoder::new(reader)),
+        Format::TarXz => Box::new(xz2::read::XzDecoder::new(reader)),
+        Format::TarZst => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        _ => Box::new(reader),
+    };
+
+    Ok(tar::Archive::new(reader))
+}
+
+#[cfg_attr(not(unix), allow(unused_variables, unused_mut))]
+fn extract_tar(
+    file_path: &Path,
+    extract_dir: &Path,
+    format: Format,
+    options: ExtractOptions,
+    progress_tx: &mpsc::Sender<Event>,
+) -> Result<Vec<PathBuf>> {
+    let name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let escapes = |kind: &str, entry: &Path| {
+        eyre!(
+            "Archive {} '{}' in {} escapes the extraction folder.",
+            kind,
+            entry.display(),
+            name
+        )
+    };
+
+    // Tar archives can only be read front to back, so the check is a full pass of its own
+    let mut entry_paths = Vec::new();
+    let mut links = Vec::new();
+
+    for entry in tar_archive(file_path, format, None)?.entries()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        let entry_path = enclosed_name(&path).ok_or_else(|| escapes("entry", &path))?;
+
+        match (entry.header().entry_type(), entry.link_name()?) {
+            (tar::EntryType::Symlink, target) => {
+                links.push((entry_path.clone(), target.unwrap_or_default().into_owned()));
+            }
+            (tar::EntryType::Link, Some(target)) if enclosed_name(&target).is_none() => {
+                return Err(escapes("hard link", &path));
+            }
+            _ => (),
+        }
+
+        entry_paths.push(entry_path);
+    }
+
+    check_not_under_links(&entry_paths, &links, &name)?;
+    check_links_enclosed(extract_dir, &links, &name)?;
+
+    let total = fs::metadata(file_path)?.len();
+    let reporter = Reporter::new(progress_tx, "Extracting", &name, total);
+    let mut archive = tar_archive(file_path, format, Some(reporter))?;
+    let mut created = Vec::new();
+
+    for (entry, entry_path) in archive.entries()?.zip(entry_paths) {
+        let mut entry = entry?;
+        let extract_path = extract_dir.join(&entry_path);
+        let entry_type = entry.header().entry_type();
+        check_no_links_on_disk(extract_dir, &entry_path, &name)?;
+
+        if entry_type.is_dir() {
+            create_dir_all(&extract_path, extract_dir, &mut created)?;
+            continue;
+        }
+
+        if let Some(parent) = extract_path.parent() {
+            create_dir_all(parent, extract_dir, &mut created)?;
+        }
+
+        match entry_type {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                replace_link(&extract_path, &mut created)?;
+                io::copy(&mut entry, &mut fs::File::create(&extract_path)?)?;
+
+                // Permission bits only, setuid and friends are never restored
+                #[cfg(unix)]
+                if options.unix_metadata {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = entry.header().mode()?;
+                    fs::set_permissions(&extract_path, fs::Permissions::from_mode(mode & 0o777))?;
+                }
+            }
+            tar::EntryType::Link => {
+                // Link targets are relative to the archive root, and were checked above
+                let target = entry.link_name()?.unwrap_or_default();
+                let target = enclosed_name(&target).unwrap_or_default();
+                check_no_links_on_disk(extract_dir, &target, &name)?;
+                match extract_path.symlink_metadata() {
+                    Ok(_) => fs::remove_file(&extract_path)?,
+                    Err(_) => created.push(extract_path.clone()),
+                }
+                fs::hard_link(extract_dir.join(target), &extract_path)?;
+            }
+            // Devices, fifos, and symlinks which are created last
+            _ => (),
+        }
+    }
+
+    // Created once every other entry is written, so nothing is written through them
+    if cfg!(unix) && options.unix_metadata {
+        create_links(extract_dir, links, &name, &mut created)?;
+    }
+    Ok(created)
+}
+
+// Zips everything in `dir`, with paths relative to it
+pub fn create_zip(dir: &Path, zip_path: &Path) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(fs::File::create(zip_path)?);
+    let result = add_to_zip(&mut zip, dir, "").and_then(|_| Ok(zip.finish().map(|_| ())?));
+
+    // Don't leave a half written archive which looks like a good backup
+    if result.is_err() {
+        let _ = fs::remove_file(zip_path);
+    }
+    result
+}
+
+fn add_to_zip(zip: &mut zip::ZipWriter<fs::File>, dir: &Path, prefix: &str) -> Result<()> {
+    let options = zip::write::SimpleFileOptions::default();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = format!("{}{}", prefix, path.file_name().unwrap().to_string_lossy());
+
+        if path.is_dir() {
+            zip.add_directory(format!("{}/", name), options)?;
+            add_to_zip(zip, &path, &format!("{}/", name))?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut fs::File::open(&path)?, zip)?;
+        }
+    }
+
+    Ok(())
+}
+
+// `fs::create_dir_all`, noting the folders which didn't exist yet. Only those at or below `root`
+// are noted, so removing the install never removes a folder it merely extracted into.
+fn create_dir_all(dir: &Path, root: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| !d.exists() && d.starts_with(root))
+        .map(Path::to_path_buf)
+        .collect();
+    fs::create_dir_all(dir)?;
+    created.extend(missing.into_iter().rev());
+    Ok(())
+}
+
+// Rejects entries listed below one of the archive's own symlinks, which would be written
+// wherever the link chain leads once the links exist
+fn check_not_under_links(
+    entries: &[PathBuf],
+    links: &[(PathBuf, PathBuf)],
+    name: &str,
+) -> Result<()> {
+    for entry in entries {
+        if let Some((link, _)) = links
+            .iter()
+            .find(|(l, _)| entry.starts_with(l) && entry != l)
+        {
+            return Err(eyre!(
+                "Archive entry '{}' in {} is inside the archive symlink '{}'.",
+                entry.display(),
+                name,
+                link.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Rejects symlinks which resolve outside `extract_dir`, following the archive's other links and
+// any already in the folder along the way, as `a/s -> ..` makes `a/l -> s/..` climb two levels
+fn check_links_enclosed(
+    extract_dir: &Path,
+    links: &[(PathBuf, PathBuf)],
+    name: &str,
+) -> Result<()> {
+    let targets: HashMap<&Path, &Path> = links
+        .iter()
+        .map(|(entry, target)| (entry.as_path(), target.as_path()))
+        .collect();
+    let read_link = |path: &Path| match targets.get(path) {
+        Some(target) => Some(target.to_path_buf()),
+        None => fs::read_link(extract_dir.join(path)).ok(),
+    };
+
+    for (entry, target) in links {
+        if !link_is_enclosed(entry, target, &read_link) {
+            return Err(eyre!(
+                "Archive symlink '{}' in {} points outside the extraction folder.",
+                entry.display(),
+                name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Rejects an entry whose parent folders under `extract_dir` include a symlink already on disk,
+// which could lead anywhere
+fn check_no_links_on_disk(extract_dir: &Path, entry: &Path, name: &str) -> Result<()> {
+    let mut path = extract_dir.to_path_buf();
+
+    for component in entry.parent().unwrap_or(Path::new("")).components() {
+        path.push(component);
+
+        match path.symlink_metadata() {
+            Ok(meta) if meta.is_symlink() => {
+                return Err(eyre!(
+                    "Archive entry '{}' in {} would be written through the symlink {}.",
+                    entry.display(),
+                    name,
+                    path.display()
+                ));
+            }
+            Ok(_) => (),
+            // Nothing further down exists yet
+            Err(_) => break,
+        }
+    }
+
+    Ok(())
+}
+
+// Removes a symlink where a file is about to be written, so it's replaced rather than written
+// through, and notes the path if it's new
+fn replace_link(path: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    match path.symlink_metadata() {
+        Ok(meta) if meta.is_symlink() => fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(_) => {
+            created.push(path.to_path_buf());
+            Ok(())
+        }
+    }
+}
+
+// Creates the archive's symlinks, last, each at an entry path relative to `extract_dir`
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn create_links(
+    extract_dir: &Path,
+    links: Vec<(PathBuf, PathBuf)>,
+    name: &str,
+    created: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for (entry_path, target) in links {
+        check_no_links_on_disk(extract_dir, &entry_path, name)?;
+        let extract_path = extract_dir.join(&entry_path);
+
+        match extract_path.symlink_metadata() {
+            Ok(meta) if !meta.is_dir() => fs::remove_file(&extract_path)?,
+            Ok(_) => (),
+            Err(_) => created.push(extract_path.clone()),
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &extract_path)?;
+    }
+
+    Ok(())
+}
+
+// `path` relative to the archive root with `.` and `..` resolved, or None if it is absolute
+// or climbs above the root. The root itself, listed as `./` by packages, is an empty path.
+fn enclosed_name(path: &Path) -> Option<PathBuf> {
+    let mut enclosed = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => enclosed.push(part),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !enclosed.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(enclosed)
+}
+
+// Whether a symlink at `entry`, relative to the archive root, resolves to somewhere inside it.
+// `read_link` gives the target of any other link met on the way, which is followed in turn.
+pub fn link_is_enclosed(
+    entry: &Path,
+    target: &Path,
+    read_link: &dyn Fn(&Path) -> Option<PathBuf>,
+) -> bool {
+    let dir = entry.parent().unwrap_or(Path::new("")).to_path_buf();
+    resolve_link(dir, target, read_link, &mut 0).is_some()
+}
+
+// `target` resolved from `dir`, or None if it leaves the root or links loop
+fn resolve_link(
+    mut dir: PathBuf,
+    target: &Path,
+    read_link: &dyn Fn(&Path) -> Option<PathBuf>,
+    hops: &mut u32,
+) -> Option<PathBuf> {
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => {
+                dir.push(part);
+                if let Some(link) = read_link(&dir) {
+                    // The kernel gives up after 40 links too
+                    *hops += 1;
+                    if *hops > 40 {
+                        return None;
+                    }
+                    dir.pop();
+                    dir = resolve_link(dir, &link, read_link, hops)?;
+                }
+            }
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !dir.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // A fresh folder under the system temp folder, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "obs-install-manager-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn extract_into(file_path: &Path, extract_dir: &Path) -> Result<Vec<PathBuf>> {
+        let (tx, _rx) = mpsc::channel();
+        extract(file_path, extract_dir, ExtractOptions::default(), &tx)
+    }
+
+    fn write_zip(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn zip_entry_climbing_out_is_rejected() {
+        let tmp = TempDir::new("zip-dotdot");
+        let zip_path = tmp.0.join("bad.zip");
+        let extract_dir = tmp.0.join("out");
+        write_zip(&zip_path, &[("ok.txt", "ok"), ("../x", "evil")]);
+
+        let err = extract_into(&zip_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'../x'"), "{}", err);
+        assert!(!extract_dir.exists());
+        assert!(!tmp.0.join("x").exists());
+    }
+
+    #[test]
+    fn zip_absolute_entry_is_rejected() {
+        let tmp = TempDir::new("zip-absolute");
+        let zip_path = tmp.0.join("bad.zip");
+        let extract_dir = tmp.0.join("out");
+        let absolute = tmp.0.join("abs.txt");
+        let absolute = absolute.to_string_lossy();
+        write_zip(&zip_path, &[("ok.txt", "ok"), (&absolute, "evil")]);
+
+        let err = extract_into(&zip_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains(&*absolute), "{}", err);
+        assert!(!extract_dir.exists());
+        assert!(!tmp.0.join("abs.txt").exists());
+    }
+
+    // a/l1 -> .., a/l1/l2 -> .., then a/l1/l2/evilzip.txt, which lands above the extraction
+    // folder if written through the links
+    #[cfg(unix)]
+    #[test]
+    fn zip_chained_symlinks_are_rejected() {
+        let tmp = TempDir::new("zip-chain");
+        let zip_path = tmp.0.join("chain.zip");
+        let extract_dir = tmp.0.join("out");
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_symlink("a/l1", "..", options).unwrap();
+        zip.add_symlink("a/l1/l2", "..", options).unwrap();
+        zip.start_file("a/l1/l2/evilzip.txt", options).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+
+        let err = extract_into(&zip_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'a/l1/l2'"), "{}", err);
+        assert!(!extract_dir.exists());
+        assert!(!tmp.0.join("evilzip.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zip_symlink_into_existing_link_is_rejected() {
+        let tmp = TempDir::new("zip-disk-link");
+        let zip_path = tmp.0.join("plugin.zip");
+        let extract_dir = tmp.0.join("out");
+        fs::create_dir_all(&extract_dir).unwrap();
+        std::os::unix::fs::symlink(&tmp.0, extract_dir.join("config")).unwrap();
+        write_zip(&zip_path, &[("config/evil.txt", "evil")]);
+
+        let err = extract_into(&zip_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("through the symlink"), "{}", err);
+        assert!(!tmp.0.join("evil.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zip_restores_symlinks_inside() {
+        let tmp = TempDir::new("zip-link");
+        let zip_path = tmp.0.join("link.zip");
+        let extract_dir = tmp.0.join("out");
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_symlink("bin/run", "../lib/run.sh", options)
+            .unwrap();
+        zip.start_file("lib/run.sh", options).unwrap();
+        zip.write_all(b"#!/bin/sh").unwrap();
+        zip.finish().unwrap();
+
+        extract_into(&zip_path, &extract_dir).unwrap();
+        let link = extract_dir.join("bin/run");
+        assert!(link.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(link).unwrap(), "#!/bin/sh");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zip_symlink_climbing_through_archive_link_is_rejected() {
+        let tmp = TempDir::new("zip-link-via-link");
+        let zip_path = tmp.0.join("via.zip");
+        let extract_dir = tmp.0.join("x/y/out");
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_symlink("a/b/s", "../..", options).unwrap();
+        zip.add_symlink("a/b/l", "s/../../..", options).unwrap();
+        zip.finish().unwrap();
+
+        let err = extract_into(&zip_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'a/b/l'"), "{}", err);
+        assert!(!extract_dir.exists());
+    }
+
+    enum TarEntry<'a> {
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+    }
+
+    // Tar bytes, written through raw headers so entries the builder would refuse can be made
+    fn tar_bytes(entries: &[TarEntry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o644);
+            match entry {
+                TarEntry::File(path, contents) => {
+                    header.set_path(path).unwrap();
+                    header.set_size(contents.len() as u64);
+                    header.set_cksum();
+                    builder.append(&header, contents.as_bytes()).unwrap();
+                }
+                TarEntry::Symlink(path, target) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_path(path).unwrap();
+                    header.set_link_name_literal(target).unwrap();
+                    header.set_size(0);
+                    header.set_cksum();
+                    builder.append(&header, io::empty()).unwrap();
+                }
+            }
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn write_tar_gz(path: &Path, entries: &[TarEntry]) {
+        let mut gz = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        gz.write_all(&tar_bytes(entries)).unwrap();
+        gz.finish().unwrap();
+    }
+
+    // The same chain as the zip one, in a tar.gz
+    #[cfg(unix)]
+    #[test]
+    fn tar_chained_symlinks_are_rejected() {
+        let tmp = TempDir::new("tar-chain");
+        let tar_path = tmp.0.join("chain.tar.gz");
+        let extract_dir = tmp.0.join("out");
+        write_tar_gz(
+            &tar_path,
+            &[
+                TarEntry::Symlink("a/l1", ".."),
+                TarEntry::Symlink("a/l1/l2", ".."),
+                TarEntry::File("a/l1/l2/evil.txt", "evil"),
+            ],
+        );
+
+        let err = extract_into(&tar_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'a/l1/l2'"), "{}", err);
+        assert!(!extract_dir.exists());
+        assert!(!tmp.0.join("evil.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tar_symlink_pointing_out_is_rejected() {
+        let tmp = TempDir::new("tar-link-out");
+        let tar_path = tmp.0.join("out.tar.gz");
+        let extract_dir = tmp.0.join("out");
+        write_tar_gz(&tar_path, &[TarEntry::Symlink("a/up", "../../..")]);
+
+        let err = extract_into(&tar_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'a/up'"), "{}", err);
+        assert!(!extract_dir.exists());
+    }
+
+    #[test]
+    fn tar_symlink_climbing_through_archive_link_is_rejected() {
+        let tmp = TempDir::new("tar-link-via-link");
+        let tar_path = tmp.0.join("via.tar.gz");
+        let extract_dir = tmp.0.join("x/y/out");
+        write_tar_gz(
+            &tar_path,
+            &[
+                TarEntry::Symlink("a/b/s", "../.."),
+                TarEntry::Symlink("a/b/l", "s/../../.."),
+            ],
+        );
+
+        let err = extract_into(&tar_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("'a/b/l'"), "{}", err);
+        assert!(!extract_dir.exists());
+    }
+
+    #[test]
+    fn tar_symlink_loop_is_rejected() {
+        let tmp = TempDir::new("tar-link-loop");
+        let tar_path = tmp.0.join("loop.tar.gz");
+        let extract_dir = tmp.0.join("out");
+        write_tar_gz(
+            &tar_path,
+            &[TarEntry::Symlink("a", "b"), TarEntry::Symlink("b", "a")],
+        );
+
+        let err = extract_into(&tar_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("points outside"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tar_extracts_files_and_links() {
+        let tmp = TempDir::new("tar-good");
+        let tar_path = tmp.0.join("good.tar.gz");
+        let extract_dir = tmp.0.join("out");
+        write_tar_gz(
+            &tar_path,
+            &[
+                TarEntry::Symlink("pkg/bin/run", "../lib/run.sh"),
+                TarEntry::File("pkg/lib/run.sh", "#!/bin/sh"),
+                // Through another of the archive's links, staying inside
+                TarEntry::Symlink("pkg/lib64", "lib"),
+                TarEntry::Symlink("pkg/bin/run64", "../lib64/../lib64/run.sh"),
+            ],
+        );
+
+        extract_into(&tar_path, &extract_dir).unwrap();
+        for link in ["pkg/bin/run", "pkg/bin/run64"] {
+            let link = extract_dir.join(link);
+            assert!(link.symlink_metadata().unwrap().is_symlink());
+            assert_eq!(fs::read_to_string(link).unwrap(), "#!/bin/sh");
+        }
+    }
+
+    // A Debian package holding `members`, after the usual debian-binary member
+    fn write_deb(path: &Path, members: &[(&str, Vec<u8>)]) {
+        let mut builder = ar::Builder::new(fs::File::create(path).unwrap());
+        let members = [("debian-binary", b"2.0\n".to_vec())]
+            .into_iter()
+            .chain(members.iter().cloned());
+
+        for (id, data) in members {
+            let header = ar::Header::new(id.as_bytes().to_vec(), data.len() as u64);
+            builder.append(&header, data.as_slice()).unwrap();
+        }
+    }
+
+    fn package_tar() -> Vec<u8> {
+        tar_bytes(&[
+            TarEntry::File("./usr/bin/obs", "#!/bin/sh"),
+            TarEntry::File("./usr/share/obs/readme.txt", "obs"),
+        ])
+    }
+
+    fn check_package_tree(extract_dir: &Path) {
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("usr/bin/obs")).unwrap(),
+            "#!/bin/sh"
+        );
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("usr/share/obs/readme.txt")).unwrap(),
+            "obs"
+        );
+    }
+
+    #[test]
+    fn deb_with_xz_data_is_extracted() {
+        let tmp = TempDir::new("deb-xz");
+        let deb_path = tmp.0.join("obs.deb");
+        let extract_dir = tmp.0.join("out");
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&package_tar()).unwrap();
+        write_deb(&deb_path, &[("data.tar.xz", xz.finish().unwrap())]);
+
+        extract_into(&deb_path, &extract_dir).unwrap();
+        check_package_tree(&extract_dir);
+        // The data member copied out next to the package is cleaned up
+        assert!(!tmp.0.join("obs.data.tar.xz").exists());
+    }
+
+    #[test]
+    fn deb_with_zst_data_is_extracted() {
+        let tmp = TempDir::new("deb-zst");
+        let deb_path = tmp.0.join("obs.deb");
+        let extract_dir = tmp.0.join("out");
+
+        let zst = zstd::encode_all(package_tar().as_slice(), 0).unwrap();
+        write_deb(
+            &deb_path,
+            &[("control.tar.xz", Vec::new()), ("data.tar.zst", zst)],
+        );
+
+        extract_into(&deb_path, &extract_dir).unwrap();
+        check_package_tree(&extract_dir);
+        assert!(!tmp.0.join("obs.data.tar.zst").exists());
+    }
+
+    #[test]
+    fn deb_without_data_is_rejected() {
+        let tmp = TempDir::new("deb-empty");
+        let deb_path = tmp.0.join("obs.deb");
+        let extract_dir = tmp.0.join("out");
+        write_deb(&deb_path, &[("control.tar.xz", Vec::new())]);
+
+        let err = extract_into(&deb_path, &extract_dir).unwrap_err();
+        assert!(err.to_string().contains("has no data archive"), "{}", err);
+        assert!(!extract_dir.exists());
+    }
+
+    #[test]
+    fn zip_extracts_nested_files() {
+        let tmp = TempDir::new("zip-good");
+        let zip_path = tmp.0.join("good.zip");
+        let extract_dir = tmp.0.join("out");
+        write_zip(&zip_path, &[("a/b.txt", "b"), ("c.txt", "c")]);
+
+        let created = extract_into(&zip_path, &extract_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("a/b.txt")).unwrap(),
+            "b"
+        );
+        assert_eq!(fs::read_to_string(extract_dir.join("c.txt")).unwrap(), "c");
+        assert_eq!(created[0], extract_dir);
+    }
+
+    #[test]
+    fn folders_above_the_extract_dir_are_not_noted() {
+        let tmp = TempDir::new("above");
+        let zip_path = tmp.0.join("good.zip");
+        let tar_path = tmp.0.join("good.tar.gz");
+        write_zip(&zip_path, &[("a/b.txt", "b")]);
+        write_tar_gz(&tar_path, &[TarEntry::File("a/b.txt", "b")]);
+
+        for (archive_path, dir) in [(&zip_path, "zip"), (&tar_path, "tar")] {
+            let extract_dir = tmp.0.join(dir).join("data/out");
+            let created = extract_into(archive_path, &extract_dir).unwrap();
+            assert!(extract_dir.join("a/b.txt").is_file());
+            assert_eq!(created[0], extract_dir);
+            assert!(created.iter().all(|p| p.starts_with(&extract_dir)));
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::source::Asset;
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Deserializer, Serialize};
+
+// Name tokens per operating system and cpu architecture. An asset naming the host's tokens
+// scores higher, an asset naming another platform's tokens is rejected, and an asset naming
+// none is neutral.
+pub const OS_TOKENS: &[(&str, &[&str])] = &[
+    ("windows", &["windows", "win", "win32", "win64"]),
+    ("macos", &["macos", "mac", "osx", "darwin"]),
+    ("linux", &["linux", "ubuntu", "debian", "fedora"]),
+];
+
+pub const ARCH_TOKENS: &[(&str, &[&str])] = &[
+    ("x86_64", &["x86_64", "x64", "amd64", "x86", "intel"]),
+    ("aarch64", &["aarch64", "arm64", "arm", "apple"]),
+];
+
+// Assets built for every architecture
+pub const UNIVERSAL_TOKENS: &[&str] = &["universal"];
+
+// Only the extension and exclusion lists are read from a manifest, the platform is the host's
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetRules {
+    #[serde(skip)]
+    pub os: &'static str,
+    #[serde(skip)]
+    pub arch: &'static str,
+    // Preferred distro tokens, e.g. ["ubuntu", "24.04"], all of which must match to score
+    #[serde(skip)]
+    pub distro: Vec<String>,
+    // Accepted file extensions, any if empty. Lowercase like the asset names they're matched
+    // against, which is also how they're read from a manifest.
+    #[serde(deserialize_with = "lowercase")]
+    pub ext: Vec<String>,
+    // Tokens which reject an asset outright
+    #[serde(deserialize_with = "lowercase")]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Candidate<'a> {
+    pub asset: &'a Asset,
+    pub score: i32,
+    pub rejected: Option<String>,
+}
+
+impl AssetRules {
+    // Rules for the platform this binary was built for
+    pub fn host() -> Self {
+        Self {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            distro: host_distro(),
+            ..Default::default()
+        }
+    }
+
+    pub fn ext<S: Into<String>>(mut self, ext: impl IntoIterator<Item = S>) -> Self {
+        self.ext = ext.into_iter().map(|e| e.into().to_lowercase()).collect();
+        self
+    }
+
+    pub fn exclude<S: Into<String>>(mut self, exclude: impl IntoIterator<Item = S>) -> Self {
+        self.exclude = exclude
+            .into_iter()
+            .map(|t| t.into().to_lowercase())
+            .collect();
+        self
+    }
+
+    pub fn score<'a>(&self, asset: &'a Asset) -> Candidate<'a> {
+        let name = asset.name.to_lowercase();
+        let reject = |reason: String| Candidate {
+            asset,
+            score: 0,
+            rejected: Some(reason),
+        };
+
+        if !self.ext.is_empty() && !self.ext.iter().any(|e| name.ends_with(&format!(".{}", e))) {
+            return reject(format!("file type is not {}", self.ext.join("/")));
+        }
+
+        if let Some(token) = self.exclude.iter().find(|t| has_token(&name, t)) {
+            return reject(format!("excluded by '{}'", token));
+        }
+
+        let mut score = 0;
+
+        match platform_match(&name, OS_TOKENS, self.os) {
+            Match::Host => score += 4,
+            Match::Other(os) => return reject(format!("built for {}", os)),
+            Match::None => (),
+        }
+
+        if UNIVERSAL_TOKENS.iter().any(|t| has_token(&name, t)) {
+            score += 1;
+        } else {
+            match platform_match(&name, ARCH_TOKENS, self.arch) {
+                Match::Host => score += 2,
+                Match::Other(arch) => return reject(format!("built for {}", arch)),
+                Match::None => (),
+            }
+        }
+
+        if !self.distro.is_empty() && self.distro.iter().all(|t| has_token(&name, t)) {
+            score += 1;
+        }
+
+        Candidate {
+            asset,
+            score,
+            rejected: None,
+        }
+    }
+
+    // Highest scoring asset, earlier assets winning ties. When nothing matches the error lists
+    // every rejected asset and why.
+    pub fn select<'a>(&self, assets: &'a [Asset]) -> Result<&'a Asset> {
+        let candidates: Vec<Candidate> = assets.iter().map(|a| self.score(a)).collect();
+
+        let best = candidates
+            .iter()
+            .filter(|c| c.rejected.is_none())
+            .rev()
+            .max_by_key(|c| c.score);
+
+        if let Some(best) = best {
+            return Ok(best.asset);
+        }
+
+        let near_misses: Vec<String> = candidates
+            .iter()
+            .filter_map(|c| Some(format!("  {}: {}", c.asset.name, c.rejected.as_ref()?)))
+            .collect();
+
+        Err(eyre!(
+            "No release asset for {}/{}{}",
+            self.os,
+            self.arch,
+            if near_misses.is_empty() {
+                ", the release has no assets.".to_string()
+            } else {
+                format!(". Rejected:\n{}", near_misses.join("\n"))
+            }
+        ))
+    }
+}
+
+fn lowercase<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let strings = Vec::<String>::deserialize(deserializer)?;
+    Ok(strings.iter().map(|s| s.to_lowercase()).collect())
+}
+
+enum Match {
+    Host,
+    Other(&'static str),
+    None,
+}
+
+fn platform_match(name: &str, table: &[(&'static str, &[&str])], host: &str) -> Match {
+    let mut other = None;
+
+    for (platform, tokens) in table {
+        if tokens.iter().any(|t| has_token(name, t)) {
+            if *platform == host {
+                return Match::Host;
+            }
+            other = other.or(Some(*platform));
+        }
+    }
+
+    other.map_or(Match::None, Match::Other)
+}
+
+// Whole word match, so "arm" doesn't match "arm64" and "mac" doesn't match "machine"
+pub fn has_token(name: &str, token: &str) -> bool {
+    name.match_indices(token).any(|(i, _)| {
+        let before = name[..i].chars().next_back();
+        let after = name[i + token.len()..].chars().next();
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && !after.is_some_and(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+// Distro id and version from /etc/os-release, e.g. ["ubuntu", "24.04"]
+#[cfg(target_os = "linux")]
+fn host_distro() -> Vec<String> {
+    let Ok(os_release) = std::fs::read_to_string("/etc/os-release") else {
+        return Vec::new();
+    };
+
+    let field = |key: &str| {
+        os_release
+            .lines()
+            .find_map(|l| l.strip_prefix(key)?.strip_prefix('='))
+            .map(|v| v.trim_matches('"').to_lowercase())
+    };
+
+    [field("ID"), field("VERSION_ID")]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn host_distro() -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> Asset {
+        Asset {
+            name: name.into(),
+            url: format!("https://example.com/{}", name),
+            size: 0,
+            sha256: None,
+            file_name: None,
+        }
+    }
+
+    #[test]
+    fn rules_match_regardless_of_case() {
+        let assets = [asset("Plugin-1.0-PDB.zip"), asset("Plugin-1.0.ZIP")];
+
+        let read: AssetRules =
+            serde_json::from_str(r#"{ "ext": ["ZIP"], "exclude": ["PDB"] }"#).unwrap();
+        assert_eq!(read.ext, ["zip"]);
+        assert_eq!(read.exclude, ["pdb"]);
+
+        let built = AssetRules::host().ext(["Zip"]).exclude(["Pdb"]);
+        for rules in [read, built] {
+            let rules = AssetRules {
+                os: "",
+                arch: "",
+                distro: Vec::new(),
+                ..rules
+            };
+            assert_eq!(rules.select(&assets).unwrap().name, "Plugin-1.0.ZIP");
+        }
+    }
+
+    // The assets of OBS Studio 31.0.2, in the order the release lists them
+    const OBS_ASSETS: &[&str] = &[
+        "OBS-Studio-31.0.2-Full-Installer-x64.exe",
+        "OBS-Studio-31.0.2-Full-x64.zip",
+        "OBS-Studio-31.0.2-Sources.tar.gz",
+        "OBS-Studio-31.0.2-Ubuntu-22.04-x86_64-dbsym.ddeb",
+        "OBS-Studio-31.0.2-Ubuntu-22.04-x86_64.deb",
+        "OBS-Studio-31.0.2-Ubuntu-24.04-x86_64-dbsym.ddeb",
+        "OBS-Studio-31.0.2-Ubuntu-24.04-x86_64.deb",
+        "OBS-Studio-31.0.2-Windows-x64-PDBs.zip",
+        "OBS-Studio-31.0.2-macOS-Apple-dSYMs.tar.xz",
+        "OBS-Studio-31.0.2-macOS-Apple.dmg",
+        "OBS-Studio-31.0.2-macOS-Intel-dSYMs.tar.xz",
+        "OBS-Studio-31.0.2-macOS-Intel.dmg",
+    ];
+
+    // Rules for a host other than this one, with the manifest's extensions and exclusions
+    fn rules(os: &'static str, arch: &'static str, distro: &[&str], ext: &str) -> AssetRules {
+        AssetRules {
+            os,
+            arch,
+            distro: distro.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        }
+        .ext([ext])
+        .exclude(["pdb", "pdbs", "dbsym", "dbgsym"])
+    }
+
+    #[test]
+    fn picks_the_obs_asset_for_each_host() {
+        let assets: Vec<Asset> = OBS_ASSETS.iter().map(|name| asset(name)).collect();
+        let cases = [
+            // The package built for the host's Ubuntu release
+            (
+                rules("linux", "x86_64", &["ubuntu", "24.04"], "deb"),
+                "OBS-Studio-31.0.2-Ubuntu-24.04-x86_64.deb",
+            ),
+            (
+                rules("linux", "x86_64", &["ubuntu", "22.04"], "deb"),
+                "OBS-Studio-31.0.2-Ubuntu-22.04-x86_64.deb",
+            ),
+            // Another distro scores both the same, so the first listed wins
+            (
+                rules("linux", "x86_64", &["fedora", "42"], "deb"),
+                "OBS-Studio-31.0.2-Ubuntu-22.04-x86_64.deb",
+            ),
+            (
+                rules("linux", "x86_64", &[], "deb"),
+                "OBS-Studio-31.0.2-Ubuntu-22.04-x86_64.deb",
+            ),
+            // Apple silicon over Intel, and the other way round
+            (
+                rules("macos", "aarch64", &[], "dmg"),
+                "OBS-Studio-31.0.2-macOS-Apple.dmg",
+            ),
+            (
+                rules("macos", "x86_64", &[], "dmg"),
+                "OBS-Studio-31.0.2-macOS-Intel.dmg",
+            ),
+            // The debug symbols name Windows, but are excluded
+            (
+                rules("windows", "x86_64", &[], "zip"),
+                "OBS-Studio-31.0.2-Full-x64.zip",
+            ),
+        ];
+
+        for (rules, expected) in cases {
+            let picked = rules.select(&assets).unwrap();
+            assert_eq!(
+                picked.name, expected,
+                "{}/{} {:?}",
+                rules.os, rules.arch, rules.distro
+            );
+        }
+    }
+
+    #[test]
+    fn lists_near_misses_when_nothing_matches() {
+        let assets: Vec<Asset> = OBS_ASSETS.iter().map(|name| asset(name)).collect();
+        let err = rules("linux", "aarch64", &["ubuntu", "24.04"], "deb")
+            .select(&assets)
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            err.starts_with("No release asset for linux/aarch64. Rejected:"),
+            "{}",
+            err
+        );
+        for line in [
+            "  OBS-Studio-31.0.2-Ubuntu-24.04-x86_64.deb: built for x86_64",
+            "  OBS-Studio-31.0.2-Ubuntu-24.04-x86_64-dbsym.ddeb: file type is not deb",
+            "  OBS-Studio-31.0.2-macOS-Apple.dmg: file type is not deb",
+        ] {
+            assert!(err.lines().any(|l| l == line), "{}", err);
+        }
+        assert_eq!(err.lines().count(), OBS_ASSETS.len() + 1);
+
+        let err = rules("linux", "x86_64", &[], "deb")
+            .select(&[])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "No release asset for linux/x86_64, the release has no assets."
+        );
+    }
+
+    #[test]
+    fn excluded_tokens_name_the_rule() {
+        let debug_symbols = asset("obs-studio_31.0.2-0obsproject1.noble_amd64-dbgsym.deb");
+        let candidate = rules("linux", "x86_64", &[], "deb").score(&debug_symbols);
+        assert_eq!(candidate.rejected.as_deref(), Some("excluded by 'dbgsym'"));
+    }
+}
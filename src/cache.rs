@@ -0,0 +1,56 @@
+use crate::file;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+pub const CACHE_DIR: &str = "cache";
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub next: Option<String>,
+    pub body: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cache {
+    pub dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            dir: file::exe_dir()?.join(CACHE_DIR),
+        })
+    }
+
+    // One file per URL, named after the URL with anything unsafe for a file name replaced
+    fn path(&self, url: &str) -> PathBuf {
+        let name: String = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(name).with_extension("json")
+    }
+
+    pub fn load(&self, url: &str) -> Option<CacheEntry> {
+        let json = fs::read_to_string(self.path(url)).ok()?;
+        let entry = serde_json::from_str::<CacheEntry>(&json).ok()?;
+        (entry.url == url).then_some(entry)
+    }
+
+    pub fn store(&self, entry: &CacheEntry) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(&entry.url), serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
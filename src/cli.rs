@@ -0,0 +1,349 @@
+use crate::{
+    app::{Event, Progress},
+    install,
+    manifest::Manifest,
+    registry::{Install, Registry},
+    ui::{ReleaseList, format_bytes},
+};
+use clap::{Parser, Subcommand};
+use color_eyre::Result;
+use serde_json::{Value, json};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+// Without a command the menu opens
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Installs and updates OBS along with its plugins and settings"
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(
+        long,
+        global = true,
+        help = "Print results as JSON, and progress as JSON lines"
+    )]
+    pub json: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Install a component, OBS unless another is named")]
+    Install {
+        #[arg(default_value = "obs")]
+        component: String,
+        #[arg(
+            long,
+            help = "Release tag to install instead of the configured version"
+        )]
+        version: Option<String>,
+        #[arg(long, help = "Only print what the install would do")]
+        dry_run: bool,
+    },
+    #[command(about = "List a component's releases, newest first")]
+    ListVersions {
+        #[arg(default_value = "obs")]
+        component: String,
+    },
+    #[command(about = "Show what is installed")]
+    Status,
+    #[command(about = "Install the configured release of installed components where it's newer")]
+    Update {
+        #[arg(help = "Component to update, every installed one if left out")]
+        component: Option<String>,
+    },
+    #[command(about = "Remove a component and everything it installed")]
+    Uninstall {
+        component: String,
+        #[arg(long, help = "Only remove the install in this folder")]
+        path: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Also remove data shared between installs, like the OBS config"
+        )]
+        shared: bool,
+    },
+    #[command(about = "Manage settings")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    #[command(about = "Zip a component's settings folder")]
+    Backup {
+        #[arg(default_value = "obs")]
+        component: String,
+        #[arg(
+            long,
+            short,
+            help = "Zip file to write, timestamped next to the executable if left out"
+        )]
+        output: Option<PathBuf>,
+    },
+}
+
+// Runs a command, printing its result to stdout and progress to stderr, and returns the exit
+// code: 0 on success, 1 on failure. Clap exits with 2 on bad arguments.
+pub fn run(command: Command, json: bool) -> i32 {
+    let (tx, rx) = mpsc::channel();
+    let logger = thread::spawn(move || log_progress(rx, json));
+    let result = execute(command, json, tx);
+    let _ = logger.join();
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            match json {
+                true => println!("{}", json!({ "error": format!("{:#}", e) })),
+                false => eprintln!("Error: {:#}", e),
+            }
+            1
+        }
+    }
+}
+
+fn execute(command: Command, json: bool, tx: Sender<Event>) -> Result<()> {
+    match command {
+        Command::Install {
+            component,
+            version,
+            dry_run,
+        } => {
+            let plan = install::build_plan(&component, version)?;
+            let value = json!({
+                "component": plan.component,
+                "version": plan.version(),
+                "actions": plan.actions,
+            });
+
+            if dry_run {
+                output(json, value, plan.actions.join("\n"));
+                return Ok(());
+            }
+
+            for action in &plan.actions {
+                log(json, json!({ "action": action }), action);
+            }
+            install::run_plan(tx, &plan)?;
+
+            let version = plan.version().unwrap_or_default();
+            output(json, value, format!("Installed {} {}", plan.name, version));
+        }
+        Command::ListVersions { component } => {
+            let releases = install::find_releases(&component)?;
+            let value = releases
+                .iter()
+                .map(|r| {
+                    json!({
+                        "tag": r.tag,
+                        "name": r.name,
+                        "published_at": r.published_at,
+                        "prerelease": r.prerelease,
+                        "draft": r.draft,
+                    })
+                })
+                .collect();
+            let lines: Vec<String> = releases.iter().map(ReleaseList::desc).collect();
+            output(json, value, lines.join("\n"));
+        }
+        Command::Status => {
+            let registry = Registry::load()?;
+            let active = |i: &Install| {
+                i.path.is_some() && registry.active.get(&i.component) == i.path.as_ref()
+            };
+
+            let value = registry
+                .installs
+                .iter()
+                .map(|i| {
+                    let mut value = serde_json::to_value(i).unwrap_or_default();
+                    value["active"] = active(i).into();
+                    value
+                })
+                .collect();
+
+            // Components with what they installed indented below them
+            let mut lines = Vec::new();
+            for install in registry.installs.iter().filter(|i| i.parent.is_none()) {
+                lines.push(status_line(install, active(install)));
+                for child in registry.installs.iter().filter(|i| {
+                    i.parent.as_ref() == Some(&install.component) && i.path == install.path
+                }) {
+                    lines.push(format!("  {}", status_line(child, false)));
+                }
+            }
+            if lines.is_empty() {
+                lines.push("Nothing installed yet".into());
+            }
+
+            output(json, value, lines.join("\n"));
+        }
+        Command::Update { component } => {
+            let components = match component {
+                Some(component) => vec![component],
+                None => installed_components()?,
+            };
+
+            let mut values = Vec::new();
+            let mut lines = Vec::new();
+
+            for component in components {
+                let updated = install::update(tx.clone(), &component)?;
+                lines.push(match &updated {
+                    Some(version) => format!("Updated {} to {}", component, version),
+                    None => format!("{} is up to date", component),
+                });
+                values.push(json!({
+                    "component": component,
+                    "updated": updated.is_some(),
+                    "version": updated,
+                }));
+            }
+
+            output(json, values.into(), lines.join("\n"));
+        }
+        Command::Uninstall {
+            component,
+            path,
+            shared,
+        } => {
+            install::uninstall(&component, path.as_deref(), shared)?;
+            output(
+                json,
+                json!({ "uninstalled": component }),
+                format!("Uninstalled {}", component),
+            );
+        }
+        Command::Config {
+            command:
+                ConfigCommand::Backup {
+                    component,
+                    output: zip_path,
+                },
+        } => {
+            let zip_path = install::backup_config(&component, zip_path)?;
+            output(
+                json,
+                json!({ "component": component, "backup": zip_path }),
+                format!("Backed up {} settings to {}", component, zip_path.display()),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// e.g. "obs 31.0.2 installed 2025-08-30 in /home/me/.local/share/... (active)"
+fn status_line(install: &Install, active: bool) -> String {
+    let name = format!("{} {}", install.component, install.version);
+    let mut line = format!(
+        "{} installed {}",
+        name.trim_end(),
+        date(install.installed_at)
+    );
+
+    if let Some(path) = &install.path {
+        line = format!("{} in {}", line, path.display());
+    }
+    if active {
+        line.push_str(" (active)");
+    }
+
+    line
+}
+
+// Components installed on their own rather than by another's steps, which still exist
+fn installed_components() -> Result<Vec<String>> {
+    let manifest = Manifest::load()?;
+    let registry = Registry::load()?;
+    let mut components: Vec<String> = Vec::new();
+
+    for install in registry.installs.iter().filter(|i| i.parent.is_none()) {
+        if manifest.component(&install.component).is_ok()
+            && !components.contains(&install.component)
+        {
+            components.push(install.component.clone());
+        }
+    }
+
+    Ok(components)
+}
+
+fn output(json: bool, value: Value, text: String) {
+    match json {
+        true => println!("{}", value),
+        false => println!("{}", text),
+    }
+}
+
+// Progress goes to stderr, keeping stdout for the result
+fn log(json: bool, value: Value, text: &str) {
+    match json {
+        true => eprintln!("{}", value),
+        false => eprintln!("{}", text),
+    }
+}
+
+// Logs every tenth of a download, extraction or copy in place of the progress bar
+fn log_progress(rx: mpsc::Receiver<Event>, json: bool) {
+    let mut last: Option<(&'static str, String, u64)> = None;
+
+    for event in rx {
+        let Event::Progress(Progress {
+            phase,
+            name,
+            done,
+            total,
+            ..
+        }) = event
+        else {
+            continue;
+        };
+
+        // Sent once something finishes
+        if total == 0 {
+            last = None;
+            continue;
+        }
+
+        let tenth = (done * 10 / total).min(10);
+        let key = (phase, name.clone(), tenth);
+
+        if last.as_ref() != Some(&key) {
+            let text = format!(
+                "{} {} {}% ({} of {})",
+                phase,
+                name,
+                tenth * 10,
+                format_bytes(done),
+                format_bytes(total)
+            );
+            let value = json!({ "phase": phase, "name": name, "done": done, "total": total });
+            log(json, value, &text);
+            last = Some(key);
+        }
+    }
+}
+
+// Seconds since the Unix epoch as a YYYY-MM-DD date, in UTC
+fn date(secs: u64) -> String {
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
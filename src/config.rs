@@ -0,0 +1,112 @@
+use crate::{
+    archive::ExtractOptions,
+    file::{self, Retry},
+    source::Channel,
+    version::VersionReq,
+};
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf, time::Duration};
+
+pub const CONFIG_FILE: &str = "obs-install-manager.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub github_token: Option<String>,
+    pub github_api_url: Option<String>,
+    // Version requirement per component id, e.g. "obs": ">=30, <32"
+    pub versions: HashMap<String, String>,
+    // Release channel per component id, stable when not listed
+    pub channels: HashMap<String, Channel>,
+    // Download attempts per URL, and the delay before the first retry which then doubles
+    pub retries: u32,
+    pub retry_delay_ms: u64,
+    // Base URLs per component id, tried in order before the original download URL
+    pub mirrors: HashMap<String, Vec<String>>,
+    // Restore unix permissions and symlinks when extracting archives
+    pub unix_metadata: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let retry = Retry::default();
+
+        Self {
+            github_token: None,
+            github_api_url: None,
+            versions: HashMap::new(),
+            channels: HashMap::new(),
+            retries: retry.attempts,
+            retry_delay_ms: retry.delay.as_millis() as u64,
+            mirrors: HashMap::new(),
+            unix_metadata: ExtractOptions::default().unix_metadata,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(file::exe_dir()?.join(CONFIG_FILE))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+
+        let mut config = if path.exists() {
+            let json = fs::read_to_string(&path)?;
+            serde_json::from_str::<Self>(&json)
+                .map_err(|e| eyre!("Config error in {}: {}", path.display(), e))?
+        } else {
+            Self::default()
+        };
+
+        // Environment takes precedence over the config file
+        if let Ok(token) = std::env::var("GITHUB_TOKEN")
+            && !token.is_empty()
+        {
+            config.github_token = Some(token);
+        }
+
+        if let Ok(api_url) = std::env::var("GITHUB_API_URL")
+            && !api_url.is_empty()
+        {
+            config.github_api_url = Some(api_url);
+        }
+
+        Ok(config)
+    }
+
+    pub fn retry(&self) -> Retry {
+        Retry {
+            attempts: self.retries,
+            delay: Duration::from_millis(self.retry_delay_ms),
+        }
+    }
+
+    pub fn extract_options(&self) -> ExtractOptions {
+        ExtractOptions {
+            unix_metadata: self.unix_metadata,
+        }
+    }
+
+    // Mirror URLs for a file, followed by the original URL
+    pub fn download_urls(&self, component: &str, file_name: &str, url: &str) -> Vec<String> {
+        let mirrors = self.mirrors.get(component).into_iter().flatten();
+        mirrors
+            .map(|m| format!("{}/{}", m.trim_end_matches('/'), file_name))
+            .chain([url.to_string()])
+            .collect()
+    }
+
+    pub fn channel(&self, component: &str) -> Channel {
+        self.channels.get(component).copied().unwrap_or_default()
+    }
+
+    pub fn version_req(&self, component: &str) -> Result<Option<VersionReq>> {
+        self.versions
+            .get(component)
+            .map(|req| req.parse::<VersionReq>())
+            .transpose()
+    }
+}
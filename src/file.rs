@@ -1,51 +1,332 @@
-use crate::app::{Event, send_progress_event};
+use crate::app::{Event, Progress, send_progress_event};
 use color_eyre::{Result, eyre::eyre};
-use curl::easy::{Easy, WriteError};
+use curl::easy::{Easy, List, WriteError};
+use sha2::{Digest, Sha256};
 use std::{
-    fs,
+    fmt, fs,
     io::{self, Write},
     path::{Path, PathBuf},
     process::{Command, ExitStatus},
     sync::mpsc,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+// Minimum time between progress events, and the window speed is measured over
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// Throttled progress events for one phase of work, clearing the progress bar when dropped
+pub struct Reporter<'a> {
+    tx: &'a mpsc::Sender<Event>,
+    progress: Progress,
+    last: (Instant, u64),
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(tx: &'a mpsc::Sender<Event>, phase: &'static str, name: &str, total: u64) -> Self {
+        Self {
+            tx,
+            progress: Progress {
+                phase,
+                name: name.into(),
+                total,
+                ..Default::default()
+            },
+            last: (Instant::now(), 0),
+        }
+    }
+
+    pub fn set_total(&mut self, total: u64) {
+        self.progress.total = total;
+    }
+
+    pub fn add(&mut self, bytes: u64) {
+        self.set(self.progress.done + bytes);
+    }
+
+    pub fn set(&mut self, done: u64) {
+        self.progress.done = done;
+
+        let elapsed = self.last.0.elapsed();
+        if self.progress.total > 0 && elapsed >= PROGRESS_INTERVAL {
+            let bytes = done.saturating_sub(self.last.1);
+            self.progress.speed = bytes as f64 / elapsed.as_secs_f64();
+            self.last = (Instant::now(), done);
+            send_progress_event(self.progress.clone(), self.tx);
+        }
+    }
+}
+
+impl Drop for Reporter<'_> {
+    fn drop(&mut self) {
+        send_progress_event(Progress::default(), self.tx);
+    }
+}
+
+// Downloads into `<path>.part` first, resuming a previous partial download with a Range
+// request, and only renames it to `path` once complete. A partial download is only resumed from
+// the URL it came from, with the ETag or Last-Modified date the server gave for it kept next to
+// it and sent as If-Range, so a newer file under the same name never gets old bytes in front.
 pub fn download<P: AsRef<Path>>(
     url: &str,
     path: P,
     progress_tx: &mpsc::Sender<Event>,
 ) -> Result<()> {
+    let path = path.as_ref();
+    let part_path = part_path(path);
+    let validator_path = validator_path(path);
+
+    let validator = read_validator(&validator_path, url);
+    if validator.is_none() {
+        remove(&part_path)?;
+    }
+    let offset = fs::metadata(&part_path).map_or(0, |m| m.len());
+
     let mut easy = Easy::new();
     easy.url(url)?;
     easy.follow_location(true)?;
+    easy.fail_on_error(true)?;
     easy.progress(true)?;
     easy.useragent("obs-install-manager-dl/1.0")?;
+    easy.resume_from(offset)?;
+
+    if let Some(validator) = validator.as_ref().filter(|_| offset > 0) {
+        let mut headers = List::new();
+        headers.append(&format!("If-Range: {}", validator))?;
+        easy.http_headers(headers)?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&part_path)?;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut reporter = Reporter::new(progress_tx, "Downloading", &name, 0);
+    let mut etag = None;
+    let mut last_modified = None;
+
+    let result = {
+        let mut transfer = easy.transfer();
+
+        transfer.write_function(move |data| {
+            file.write_all(data).map_err(|_| WriteError::Pause)?;
+            Ok(data.len())
+        })?;
+
+        transfer.header_function(|header| {
+            let header = String::from_utf8_lossy(header);
+
+            // Each redirect starts a new set of headers
+            if header.starts_with("HTTP/") {
+                (etag, last_modified) = (None, None);
+            } else if let Some((key, value)) = header.split_once(':') {
+                let value = value.trim().to_string();
+                // Weak ETags can't be used with If-Range
+                if key.eq_ignore_ascii_case("etag") && !value.starts_with("W/") {
+                    etag = Some(value);
+                } else if key.eq_ignore_ascii_case("last-modified") {
+                    last_modified = Some(value);
+                }
+            }
+            true
+        })?;
 
-    let mut file = fs::File::create(path)?;
-    let mut transfer = easy.transfer();
+        // Totals only cover the part still being downloaded
+        transfer.progress_function(|dltotal, dlnow, _, _| {
+            if dltotal > 0.0 {
+                reporter.set_total(offset + dltotal as u64);
+                reporter.set(offset + dlnow as u64);
+            }
+            true
+        })?;
+
+        transfer.perform()
+    };
+
+    drop(reporter);
+    let code = easy.response_code()?;
+
+    // Asked for the rest of a file which is already all there
+    if offset > 0 && code == 416 {
+        fs::rename(&part_path, path)?;
+        remove(&validator_path)?;
+        return Ok(());
+    }
 
-    transfer.write_function(move |data| {
-        file.write_all(data).map_err(|_| WriteError::Pause)?;
-        Ok(data.len())
-    })?;
+    // The file changed since the partial download, or the server can't resume it, so the
+    // whole file was sent or refused. Start over.
+    let restarted = code == 200 || result.as_ref().is_err_and(|e| e.is_range_error());
+    if offset > 0 && restarted {
+        remove(&part_path)?;
+        remove(&validator_path)?;
+        return download(url, path, progress_tx);
+    }
 
-    transfer.progress_function(|dltotal, dlnow, _, _| {
-        if dltotal > 0.0 {
-            send_progress_event(dlnow / dltotal, progress_tx);
+    if let Err(e) = result {
+        // Kept so the next attempt can resume, as long as the server said which file it was
+        match etag.or(last_modified) {
+            Some(validator) => fs::write(&validator_path, format!("{}\n{}", url, validator))?,
+            None => remove(&validator_path)?,
         }
-        true
-    })?;
 
-    transfer.perform()?;
-    send_progress_event(0.0, progress_tx);
+        if e.is_http_returned_error() {
+            return Err(HttpError(code).into());
+        }
+        return Err(e.into());
+    }
+
+    fs::rename(&part_path, path)?;
+    remove(&validator_path)?;
     Ok(())
 }
 
-pub fn copy_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+// Where the URL and validator of a partial download are kept
+fn validator_path(path: &Path) -> PathBuf {
+    let mut validator_path = part_path(path).into_os_string();
+    validator_path.push(".validator");
+    PathBuf::from(validator_path)
+}
+
+// The validator saved for a partial download of `url`, None if it came from elsewhere
+fn read_validator(validator_path: &Path, url: &str) -> Option<String> {
+    let contents = fs::read_to_string(validator_path).ok()?;
+    let (saved_url, validator) = contents.split_once('\n')?;
+    (saved_url == url && !validator.is_empty()).then(|| validator.to_string())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpError(pub u32);
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {} while downloading", self.0)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+// Tries each URL in order, retrying network errors and 5xx responses with exponential
+// backoff. Partial downloads carry over between attempts at the same URL.
+pub fn download_retry<P: AsRef<Path>>(
+    urls: &[String],
+    path: P,
+    progress_tx: &mpsc::Sender<Event>,
+    retry: Retry,
+) -> Result<()> {
+    let mut last_error = eyre!("No download URL given.");
+
+    for url in urls {
+        for attempt in 0..retry.attempts.max(1) {
+            if attempt > 0 {
+                thread::sleep(retry.delay * 2u32.pow(attempt.min(5) - 1));
+            }
+
+            match download(url, path.as_ref(), progress_tx) {
+                Ok(()) => return Ok(()),
+                Err(e) if is_transient(&e) => last_error = e,
+                Err(e) => {
+                    last_error = e;
+                    break;
+                }
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+fn is_transient(e: &color_eyre::Report) -> bool {
+    if let Some(HttpError(code)) = e.downcast_ref::<HttpError>() {
+        return *code >= 500 || *code == 429;
+    }
+
+    e.downcast_ref::<curl::Error>().is_some_and(|e| {
+        e.is_couldnt_resolve_host()
+            || e.is_couldnt_connect()
+            || e.is_operation_timedout()
+            || e.is_partial_file()
+            || e.is_recv_error()
+            || e.is_send_error()
+            || e.is_got_nothing()
+            || e.is_ssl_connect_error()
+    })
+}
+
+// Where the config, manifest, registry and cache live, as does everything else this tool manages
+// unless a component says otherwise
+pub fn exe_dir() -> io::Result<PathBuf> {
+    let exe_path = std::env::current_exe()?;
+    Ok(exe_path.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+pub fn part_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut part_path = path.as_ref().as_os_str().to_owned();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+// Lowercase hex SHA-256 of a file's contents
+pub fn sha256<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Deletes the file when it doesn't match, so a bad download is never extracted or run
+pub fn verify_sha256<P: AsRef<Path>>(path: P, expected: &str) -> Result<()> {
+    let path = path.as_ref();
+    let actual = sha256(path)?;
+
+    if actual.eq_ignore_ascii_case(expected) {
+        return Ok(());
+    }
+
+    fs::remove_file(path)?;
+    Err(eyre!(
+        "Checksum mismatch for {}, expected SHA-256 {} but got {}. The file was deleted.",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        expected,
+        actual
+    ))
+}
+
+// Returns the files and folders which didn't exist before, overwritten files aren't included
+pub fn copy_dir(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    phase: &'static str,
+    progress_tx: &mpsc::Sender<Event>,
+) -> io::Result<Vec<PathBuf>> {
     let src = src.as_ref();
-    let dst = dst.as_ref();
+    let name = src.file_name().unwrap_or_default().to_string_lossy();
+    let mut reporter = Reporter::new(progress_tx, phase, &name, dir_size(src)?);
+    let mut created = Vec::new();
+    copy_dir_inner(src, dst.as_ref(), &mut reporter, &mut created)?;
+    Ok(created)
+}
 
+fn copy_dir_inner(
+    src: &Path,
+    dst: &Path,
+    reporter: &mut Reporter,
+    created: &mut Vec<PathBuf>,
+) -> io::Result<()> {
     if !src.is_dir() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
@@ -53,6 +334,9 @@ pub fn copy_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()>
         ));
     }
 
+    if !dst.exists() {
+        created.push(dst.to_path_buf());
+    }
     fs::create_dir_all(dst)?;
 
     for entry in fs::read_dir(src)? {
@@ -62,41 +346,68 @@ pub fn copy_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()>
         let dst_path: PathBuf = dst.join(entry.file_name());
 
         if file_type.is_dir() {
-            copy_dir(&src_path, &dst_path)?;
+            copy_dir_inner(&src_path, &dst_path, reporter, created)?;
         } else if file_type.is_file() {
-            fs::copy(&src_path, &dst_path)?;
+            if !dst_path.exists() {
+                created.push(dst_path.clone());
+            }
+            reporter.add(fs::copy(&src_path, &dst_path)?);
         }
     }
 
     Ok(())
 }
 
-// TODO: Set up progress bar for extract
-pub fn extract_zip<P: AsRef<Path>>(file_path: P, extract_dir: P) -> Result<()> {
-    let mut archive = zip::ZipArchive::new(io::BufReader::new(fs::File::open(file_path)?))?;
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let extract_path = extract_dir.as_ref().join(file.name());
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
 
-        if file.name().ends_with('/') {
-            std::fs::create_dir_all(&extract_path)?;
-        } else {
-            if let Some(parent) = extract_path.parent() {
-                std::fs::create_dir_all(parent)?;
+            if file_type.is_dir() {
+                size += dir_size(&entry.path())?;
+            } else if file_type.is_file() {
+                size += entry.metadata()?.len();
             }
-            io::copy(&mut file, &mut fs::File::create(&extract_path)?)?;
         }
     }
 
-    Ok(())
+    Ok(size)
+}
+
+// Removes a file, folder or link, doing nothing if it doesn't exist
+pub fn remove(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+
+    match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        // Windows treats links to folders as folders
+        Ok(meta) if cfg!(windows) && meta.is_symlink() && path.is_dir() => fs::remove_dir(path),
+        Ok(_) => fs::remove_file(path),
+    }
+}
+
+pub fn symlink_dir(target: impl AsRef<Path>, link: impl AsRef<Path>) -> io::Result<()> {
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_dir(target, link);
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
 }
 
 pub fn run<P: AsRef<Path>>(path: P) -> io::Result<ExitStatus> {
-    Ok(Command::new(path.as_ref().as_os_str()).spawn()?.wait()?)
+    Command::new(path.as_ref().as_os_str()).spawn()?.wait()
 }
 
-pub fn install_dmg(dmg_path: &str, mount_tag: &str) -> Result<()> {
+// Copies the app out of a disk image into /Applications, returning where it went
+pub fn install_dmg(
+    dmg_path: &str,
+    mount_tag: &str,
+    replace: impl FnOnce(&Path) -> io::Result<()>,
+) -> Result<PathBuf> {
     Command::new("open").arg(dmg_path).status()?;
 
     let mount_point = wait_for_mount(mount_tag, 30)?;
@@ -104,21 +415,22 @@ pub fn install_dmg(dmg_path: &str, mount_tag: &str) -> Result<()> {
     let app_src = format!("{}/{}", mount_point, app_name);
     let app_dst = format!("/Applications/{}", app_name);
 
-    if !fs::exists(&app_dst).unwrap() {
-        let result = Command::new("cp")
-            .args(["-R", &app_src, &app_dst])
-            .status()?;
+    // An app already there is handed to `replace` to move out of the way, so the copy is always
+    // the one in the image
+    replace(Path::new(&app_dst))?;
+    let result = Command::new("cp")
+        .args(["-R", &app_src, &app_dst])
+        .status()?;
 
-        if !result.success() {
-            return Err(eyre!("Failed to copy app"));
-        }
+    if !result.success() {
+        return Err(eyre!("Failed to copy app"));
     }
 
     Command::new("hdiutil")
         .args(["detach", &mount_point])
         .status()?;
 
-    Ok(())
+    Ok(PathBuf::from(app_dst))
 }
 
 fn wait_for_mount(mount_tag: &str, max_attempts: u32) -> Result<String> {
@@ -168,3 +480,143 @@ fn find_app(mount_point: &str) -> Result<String> {
         .next()
         .ok_or_else(|| eyre!("No .app bundle found in mounted volume"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    const BODY: &str = "the new release, all of it";
+    const ETAG: &str = "\"v2\"";
+
+    // Serves BODY with an ETag, honouring Range only while If-Range matches, and records the
+    // Range header of each request
+    fn serve() -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/plugin.zip", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                let mut if_range = None;
+
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((key, value)) = line.split_once(": ") {
+                        match key.to_lowercase().as_str() {
+                            "range" => range = Some(value.to_string()),
+                            "if-range" => if_range = Some(value.to_string()),
+                            _ => (),
+                        }
+                    }
+                }
+                seen.lock().unwrap().push(range.clone());
+
+                let start = range
+                    .filter(|_| if_range.as_deref().is_none_or(|v| v == ETAG))
+                    .and_then(|r| r.strip_prefix("bytes=")?.trim_end_matches('-').parse().ok());
+                let response = match start {
+                    Some(start) if start >= BODY.len() => format!(
+                        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\n\
+                         Content-Length: 0\r\nConnection: close\r\n\r\n",
+                        BODY.len()
+                    ),
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nETag: {}\r\n\
+                         Content-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        ETAG,
+                        start,
+                        BODY.len() - 1,
+                        BODY.len(),
+                        BODY.len() - start,
+                        &BODY[start..]
+                    ),
+                    None => format!(
+                        "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{}",
+                        ETAG,
+                        BODY.len(),
+                        BODY
+                    ),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (url, ranges)
+    }
+
+    // A partial download left behind, with the validator it was saved under
+    fn leave_part(name: &str, part: &str, url: &str, validator: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "obs-install-manager-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plugin.zip");
+        fs::write(part_path(&path), part).unwrap();
+        fs::write(validator_path(&path), format!("{}\n{}", url, validator)).unwrap();
+        path
+    }
+
+    fn download_into(url: &str, path: &Path) {
+        let (tx, _rx) = mpsc::channel();
+        download(url, path, &tx).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), BODY);
+        assert!(!part_path(path).exists());
+        assert!(!validator_path(path).exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn resumes_the_same_file() {
+        let (url, ranges) = serve();
+        let path = leave_part("resume", &BODY[..8], &url, ETAG);
+        download_into(&url, &path);
+        assert_eq!(*ranges.lock().unwrap(), [Some("bytes=8-".to_string())]);
+    }
+
+    #[test]
+    fn starts_over_when_the_file_changed() {
+        let (url, ranges) = serve();
+        let path = leave_part("changed", "the old release", &url, "\"v1\"");
+        download_into(&url, &path);
+        assert_eq!(
+            *ranges.lock().unwrap(),
+            [Some("bytes=15-".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn starts_over_for_another_url() {
+        let (url, ranges) = serve();
+        let path = leave_part(
+            "mirror",
+            "the old release",
+            "http://mirror/plugin.zip",
+            ETAG,
+        );
+        download_into(&url, &path);
+        assert_eq!(*ranges.lock().unwrap(), [None]);
+    }
+
+    #[test]
+    fn finished_part_is_complete() {
+        let (url, _) = serve();
+        let path = leave_part("finished", BODY, &url, ETAG);
+        download_into(&url, &path);
+    }
+}
@@ -1,23 +1,37 @@
+use crate::{
+    cache::{Cache, CacheEntry},
+    config::Config,
+};
 use color_eyre::{Result, eyre::eyre};
-use reqwest::{IntoUrl, blocking::Client};
+use reqwest::{
+    StatusCode,
+    blocking::{Client, Response},
+    header::{self, HeaderMap, HeaderValue, LINK},
+};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
 
-pub const GIT_REPO_API: &str = "https://api.github.com/repos";
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+
+// GitHub caps page size at 100, page cap guards against a misbehaving Link header
+pub const PER_PAGE: u32 = 100;
+pub const MAX_PAGES: u32 = 50;
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct GithubRepo {
-    pub author: &'static str,
-    pub name: &'static str,
+    pub author: String,
+    pub name: String,
 }
 
 impl GithubRepo {
-    pub fn url(&self) -> PathBuf {
-        PathBuf::new()
-            .join(GIT_REPO_API)
-            .join(self.author)
-            .join(self.name)
-            .join("releases")
+    pub fn url(&self, api_url: &Url) -> Result<Url> {
+        let mut url = api_url.clone();
+        url.path_segments_mut()
+            .map_err(|_| eyre!("API URL cannot be a base: {}", api_url))?
+            .pop_if_empty()
+            .extend(["repos", &self.author, &self.name, "releases"]);
+        Ok(url)
     }
 }
 
@@ -58,6 +72,9 @@ pub struct GithubAsset {
     pub created_at: String,
     pub updated_at: String,
     pub browser_download_url: String,
+    // "sha256:<hex>", only present on assets uploaded since GitHub started computing them
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
@@ -83,66 +100,173 @@ pub struct GithubAuthor {
     pub site_admin: bool,
 }
 
-#[derive(Default, Clone, Debug)]
-pub struct GithubApiClient(Client);
+#[derive(Clone, Debug)]
+pub struct GithubApiClient {
+    api_url: Url,
+    client: Client,
+    cache: Cache,
+}
 
 impl GithubApiClient {
     pub fn new() -> Result<Self> {
-        Ok(Self(
-            reqwest::blocking::Client::builder()
+        let config = Config::load()?;
+        let api_url = config.github_api_url.as_deref().unwrap_or(GITHUB_API_URL);
+        Self::with_options(api_url, config.github_token.as_deref())
+    }
+
+    pub fn with_options(api_url: &str, token: Option<&str>) -> Result<Self> {
+        let api_url =
+            Url::parse(api_url).map_err(|e| eyre!("Invalid API URL '{}': {}", api_url, e))?;
+
+        let mut headers = HeaderMap::new();
+
+        if let Some(token) = token {
+            let mut auth = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| eyre!("GitHub token contains invalid characters."))?;
+            auth.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, auth);
+        }
+
+        Ok(Self {
+            api_url,
+            client: reqwest::blocking::Client::builder()
                 .user_agent("github-api-client/1.0")
+                .default_headers(headers)
                 .build()?,
-        ))
+            cache: Cache::new()?,
+        })
     }
 
     pub fn get_releases(&self, repo: &GithubRepo) -> Result<Vec<GithubRelease>> {
-        let url = repo.url();
-        let url = url.to_str().expect("GithubRepo is not valid unicode.");
-        self.parse_json::<Vec<GithubRelease>>(url)
+        let mut url = repo.url(&self.api_url)?;
+        url.query_pairs_mut()
+            .append_pair("per_page", &PER_PAGE.to_string());
+
+        let mut releases = Vec::new();
+        let mut next = Some(url.to_string());
+
+        for _ in 0..MAX_PAGES {
+            let Some(url) = next.take() else { break };
+            let page = self.fetch(&url)?;
+            next = page.next.clone();
+            releases.extend(Self::decode_json::<Vec<GithubRelease>>(&page)?);
+        }
+
+        Ok(releases)
     }
 
     pub fn get_release(&self, repo: &GithubRepo, version: Option<String>) -> Result<GithubRelease> {
-        let mut url = repo.url();
+        let mut url = repo.url(&self.api_url)?;
 
-        if let Some(version) = version.as_ref() {
-            url.push(version);
-        } else {
-            url.push("latest");
+        if let Ok(mut segments) = url.path_segments_mut() {
+            match version.as_ref() {
+                Some(version) => segments.extend(["tags", version]),
+                None => segments.push("latest"),
+            };
         }
 
-        let url = url.to_str().expect("GithubRepo is not valid unicode.");
-        self.parse_json::<GithubRelease>(url)
+        self.parse_json::<GithubRelease>(url.as_str())
+    }
+
+    fn parse_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        Self::decode_json(&self.fetch(url)?)
+    }
+
+    fn decode_json<T: DeserializeOwned>(page: &CacheEntry) -> Result<T> {
+        serde_json::from_str::<T>(&page.body).map_err(|e| eyre!("JSON decode error: {}", e))
     }
 
+    // Revalidates against the on-disk cache with If-None-Match, serving the cached copy on
+    // 304, and also when the network is down or the rate limit is spent
+    fn fetch(&self, url: &str) -> Result<CacheEntry> {
+        let cached = self.cache.load(url);
+
+        let mut request = self.client.get(url);
+        if let Some(etag) = cached.as_ref().and_then(|c| c.etag.as_ref()) {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+
+        let response = match (request.send(), cached) {
+            (Ok(response), Some(cached)) if serve_cached(response.status()) => return Ok(cached),
+            (Ok(response), _) => response,
+            (Err(_), Some(cached)) => return Ok(cached),
+            (Err(e), None) => return Err(e.into()),
+        };
+
+        let response = Self::check_status(response)?;
+        let entry = CacheEntry {
+            url: url.to_string(),
+            etag: response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string()),
+            next: next_link(response.headers()),
+            body: response.text()?,
+        };
+
+        // A cache that can't be written is not worth failing the request over
+        let _ = self.cache.store(&entry);
+        Ok(entry)
+    }
 
     #[rustfmt::skip]
-    fn parse_json<T: DeserializeOwned>(&self, url: impl IntoUrl) -> Result<T> {
-        let response = self.0.get(url).send()?;
+    fn check_status(response: Response) -> Result<Response> {
         match response.status() {
-            reqwest::StatusCode::OK => response.json::<T>().map_err(|e| eyre!("JSON decode error: {}", e)),
-            reqwest::StatusCode::NOT_FOUND => Err(eyre!("(404) Repository not found.")),
-            reqwest::StatusCode::FORBIDDEN => Err(eyre!("(403) Rate limited or access denied.")),
+            StatusCode::OK => Ok(response),
+            StatusCode::NOT_FOUND => Err(eyre!("(404) Repository not found.")),
+            StatusCode::UNAUTHORIZED => Err(eyre!("(401) GitHub token was rejected.")),
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => Err(rate_limit_error(&response)),
             status => Err(eyre!("HTTP {}: {}", status, response.text()?)),
         }
     }
 }
 
-impl GithubRelease {
-    #[rustfmt::skip]
-    pub fn get_assets(
-        self,
-        incl: Option<Vec<&'static str>>,
-        excl: Option<Vec<&'static str>>,
-        arch: Option<Vec<&'static str>>,
-    ) -> Vec<GithubAsset> {
-        self.assets
-            .into_iter()
-            .filter(|asset| {
-                let n = asset.name.to_lowercase();
-                incl.as_ref().map_or(true, |i| i.iter().all(|s|  n.contains(s))) &&
-                excl.as_ref().map_or(true, |e| e.iter().all(|s| !n.contains(s))) &&
-                arch.as_ref().map_or(true, |a| a.iter().any(|s|  n.contains(s)))
-            })
-            .collect()
-    }
+fn serve_cached(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::NOT_MODIFIED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    )
+}
+
+// Builds a 403/429 error which says when the rate limit resets, if that is the cause
+fn rate_limit_error(response: &Response) -> color_eyre::eyre::Report {
+    let status = response.status().as_u16();
+    let header =
+        |name: &str| -> Option<u64> { response.headers().get(name)?.to_str().ok()?.parse().ok() };
+
+    let (Some(0), Some(reset)) = (header("x-ratelimit-remaining"), header("x-ratelimit-reset"))
+    else {
+        return eyre!("({}) Access denied.", status);
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mins = reset.saturating_sub(now).div_ceil(60);
+    let limit = header("x-ratelimit-limit").map_or(String::new(), |l| format!(" of {}", l));
+
+    eyre!(
+        "({}) Rate limit{} requests exceeded, resets in {} min. Set GITHUB_TOKEN to raise the limit.",
+        status,
+        limit,
+        mins
+    )
+}
+
+// Parses `<url>; rel="next"` out of a GitHub pagination Link header
+pub fn next_link(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get(LINK)?.to_str().ok()?;
+
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        let is_next = params.split(';').any(|p| p.trim() == "rel=\"next\"");
+        is_next.then(|| {
+            url.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
 }
@@ -1,275 +1,1106 @@
 #[cfg(windows)]
 use crate::scut;
-use crate::{app::Event, file, git::*};
-use color_eyre::{Result, eyre::OptionExt};
-use std::{fs, os, sync::mpsc::Sender};
-
-// OBS (Open Broadcast Software)
-pub fn obs(tx: Sender<Event>) -> Result<()> {
-    let github_api_client = GithubApiClient::new()?;
-
-    // Search tags per operating system
-    #[cfg(target_os = "windows")]
-    let (incl, excl) = (vec!["windows", "zip"], vec!["pdb"]);
-    #[cfg(target_os = "macos")]
-    let (incl, excl) = (vec!["macos", "dmg"], vec!["tar"]);
-    #[cfg(target_os = "linux")]
-    let (incl, excl) = (vec!["ubuntu", "deb"], vec!["ddeb"]);
-
-    // Search tags per cpu architecture
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    let arch = vec!["intel", "x86", "x64"];
-    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-    let arch = vec!["arm", "apple"];
-
-    // Get latest asset infos
-    let git_release = github_api_client.get_release(&crate::OBS_REPO, None)?;
-    let git_assets = git_release.get_assets(Some(incl), Some(excl), Some(arch));
-    let git_asset = git_assets.first().ok_or_eyre("Git asset not found!")?;
-
-    // Build paths
-    let exe_path = std::env::current_exe()?;
-    let exe_dir = exe_path.parent().unwrap();
-    let asset_path = exe_dir.join(&git_asset.name);
-    let asset_name = asset_path.file_stem().unwrap();
-
-    // Download asset
-    if asset_path.exists() {
-        fs::remove_file(&asset_path)?;
-    }
-    file::download(&git_asset.browser_download_url, &asset_path, &tx)?;
-
-    // Windows setup
-    #[cfg(target_os = "windows")]
-    {
-        // Extract zip
-        let asset_dir = exe_dir.join(&asset_name);
-        if asset_dir.exists() {
-            fs::remove_dir_all(&asset_dir)?;
+use crate::{
+    app::{Event, send_installs_event, send_plan_event, send_releases_event},
+    archive,
+    asset::AssetRules,
+    config::Config,
+    file,
+    manifest::{Component, Manifest, SideBySide, Step},
+    registry::{Install, Registry},
+    source::*,
+    transaction::{self, Transaction},
+    ui::format_bytes,
+    version::Version,
+};
+use color_eyre::{Result, eyre::eyre};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{self, Path, PathBuf},
+    sync::mpsc::{self, Sender},
+};
+use url::Url;
+
+// Installs a component at a release tag, or its configured version if none
+pub fn install(tx: Sender<Event>, component: &str, version: Option<String>) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let mut ctx = Context::new(&manifest, &tx)?;
+    let result = ctx.install(component, version);
+    ctx.finish(result, &component.name)
+}
+
+// Every action installing a component would take, without taking them, and the releases it
+// would install, so running the plan installs exactly what was shown
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub component: String,
+    pub name: String,
+    pub actions: Vec<String>,
+    // Release tag per component id
+    versions: HashMap<String, String>,
+}
+
+impl Plan {
+    // The release the plan installs, if the component has a source
+    pub fn version(&self) -> Option<&str> {
+        self.versions.get(&self.component).map(String::as_str)
+    }
+}
+
+pub fn build_plan(component: &str, version: Option<String>) -> Result<Plan> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let (tx, _rx) = mpsc::channel();
+    let mut ctx = Context::new(&manifest, &tx)?;
+    ctx.dry_run = true;
+    ctx.install(component, version)?;
+
+    Ok(Plan {
+        component: component.id.clone(),
+        name: component.name.clone(),
+        actions: ctx.plan,
+        versions: ctx.versions,
+    })
+}
+
+// Sends the plan for installing a component to be confirmed
+pub fn plan(tx: Sender<Event>, component: &str, version: Option<String>) -> Result<()> {
+    send_plan_event(build_plan(component, version)?, &tx);
+    Ok(())
+}
+
+pub fn run_plan(tx: Sender<Event>, plan: &Plan) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(&plan.component)?;
+    let mut ctx = Context::new(&manifest, &tx)?;
+    ctx.versions = plan.versions.clone();
+    let result = ctx.install(component, None);
+    ctx.finish(result, &component.name)
+}
+
+// A component's releases, newest first
+pub fn find_releases(component: &str) -> Result<Vec<Release>> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let source = component
+        .source
+        .as_ref()
+        .ok_or_else(|| eyre!("{} has no releases to choose from.", component.name))?;
+
+    let mut releases = source.release_source()?.releases()?;
+    sort_releases(&mut releases);
+    Ok(releases)
+}
+
+// Sends a component's release list to the version picker
+pub fn releases(tx: Sender<Event>, component: &str) -> Result<()> {
+    send_releases_event(find_releases(component)?, component.into(), &tx);
+    Ok(())
+}
+
+// Installs a component's configured release if it isn't installed already and is newer than
+// every install of it, returning the release tag
+pub fn update(tx: Sender<Event>, component: &str) -> Result<Option<String>> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let Some(source) = &component.source else {
+        return Ok(None);
+    };
+
+    let registry = Registry::load()?;
+    let installs: Vec<&Install> = registry
+        .installs_of(&component.id)
+        .filter(|i| i.parent.is_none())
+        .collect();
+
+    if installs.is_empty() {
+        return Err(eyre!("{} isn't installed.", component.name));
+    }
+
+    let release = resolve(source.release_source()?.as_ref(), &component.id, None)?;
+    let newest = installs
+        .iter()
+        .filter_map(|i| i.version.parse::<Version>().ok())
+        .max();
+    let current = installs.iter().any(|i| i.version == release.tag)
+        || newest.is_some_and(|newest| release.version().is_none_or(|v| v <= newest));
+
+    if current {
+        return Ok(None);
+    }
+
+    let plan = build_plan(&component.id, Some(release.tag.clone()))?;
+    run_plan(tx, &plan)?;
+    Ok(Some(release.tag))
+}
+
+// Zips a component's settings folder into `zip_path`, or a timestamped file next to the
+// executable, returning where it went
+pub fn backup_config(component: &str, zip_path: Option<PathBuf>) -> Result<PathBuf> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let config_dir = component
+        .config_dir
+        .as_ref()
+        .ok_or_else(|| eyre!("{} has no settings to back up.", component.name))?;
+
+    let vars = base_vars()?;
+    let config_dir = expand_path(config_dir, &vars);
+
+    if !config_dir.is_dir() {
+        return Err(eyre!("No settings found in {}.", config_dir.display()));
+    }
+
+    let zip_path = zip_path.unwrap_or_else(|| {
+        let name = format!("{}-config-{}.zip", component.id, Install::now());
+        Path::new(&vars["exe_dir"]).join(name)
+    });
+    archive::create_zip(&config_dir, &zip_path)?;
+    Ok(zip_path)
+}
+
+// Removes what a component installed, from every install of it unless `path` picks one, and
+// what its steps installed alongside. Shared data such as the OBS config is kept unless
+// `shared` is set.
+pub fn uninstall(component: &str, path: Option<&Path>, shared: bool) -> Result<()> {
+    let mut registry = Registry::load()?;
+    remove_installs(&mut registry, component, path, shared)?;
+    registry.save()
+}
+
+// Deletes the files of the matching installs and drops them from `registry`, which is left for
+// the caller to save
+fn remove_installs(
+    registry: &mut Registry,
+    component: &str,
+    path: Option<&Path>,
+    shared: bool,
+) -> Result<()> {
+    let removed = registry.take(component, path, shared);
+
+    if removed.is_empty() {
+        return Err(eyre!("{} is not installed.", component));
+    }
+    // Like the Kilohearts and ReaPlugs installers, which put their files wherever they like
+    if removed.iter().all(|i| i.files.is_empty()) {
+        return Err(eyre!(
+            "{} was installed by its own installer, there is nothing to remove. Use its \
+             uninstaller instead.",
+            component
+        ));
+    }
+
+    // Children first, and within an install the files inside a folder before the folder
+    for install in removed.iter().rev() {
+        for path in install.files.iter().rev() {
+            // Kept while another install lists it or something inside it, like a shortcut
+            let in_use = registry
+                .installs
+                .iter()
+                .any(|i| i.files.iter().any(|f| f.starts_with(path)));
+
+            if !in_use {
+                file::remove(path)?;
+            }
         }
-        file::extract_zip(&asset_path, &asset_dir)?;
-        fs::remove_file(&asset_path)?;
+    }
+
+    registry.active.retain(|component, path| {
+        !removed
+            .iter()
+            .any(|i| &i.component == component && i.path.as_ref() == Some(path))
+    });
+    Ok(())
+}
+
+// Every side by side install of a component, recorded or found on disk, newest first, and the
+// folder of the active one
+pub fn find_installs(component: &str) -> Result<(Vec<Install>, Option<PathBuf>)> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let side_by_side = side_by_side(component)?;
+    let registry = Registry::load()?;
+    let vars = base_vars()?;
+
+    let mut installs: Vec<Install> = registry
+        .installs_of(&component.id)
+        .filter(|i| i.parent.is_none() && i.path.as_ref().is_some_and(|p| p.exists()))
+        .cloned()
+        .collect();
 
-        // Enable portable mode
-        fs::File::create(asset_dir.join("portable_mode"))?;
+    // Folders installed before the registry existed, or by hand
+    for dir in &side_by_side.dirs {
+        let Ok(entries) = fs::read_dir(expand_path(dir, &vars)) else {
+            continue;
+        };
 
-        // Setup config folder
-        let cfg_dir = exe_dir.join("obs-config");
-        if !cfg_dir.exists() {
-            fs::create_dir(&cfg_dir)?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let known = installs.iter().any(|i| i.path.as_ref() == Some(&path));
+            if known || transaction::is_backup(&path) {
+                continue;
+            }
+
+            // Not following links, which may point at the active install
+            if entry.file_type()?.is_dir() && path.join(&side_by_side.marker).exists() {
+                installs.push(Install {
+                    component: component.id.clone(),
+                    version: version_in_name(&path),
+                    path: Some(path.clone()),
+                    files: vec![path],
+                    ..Default::default()
+                });
+            }
         }
-        os::windows::fs::symlink_dir(&cfg_dir, &asset_dir.join("config"))?;
+    }
+
+    installs.sort_by_cached_key(|i| std::cmp::Reverse(i.version.parse::<Version>().ok()));
+    let active = registry.active.get(&component.id).filter(|p| p.exists());
+    Ok((installs, active.cloned()))
+}
+
+// Sends a component's side by side installs to the installed versions screen
+pub fn installs(tx: Sender<Event>, component: &str) -> Result<()> {
+    let (installs, active) = find_installs(component)?;
+    send_installs_event(component.into(), installs, active, &tx);
+    Ok(())
+}
+
+// Makes the install in `path` the one which gets launched
+pub fn activate(tx: Sender<Event>, component: &str, path: &Path) -> Result<()> {
+    let manifest = Manifest::load()?;
+    let component = manifest.component(component)?;
+    let mut ctx = Context::new(&manifest, &tx)?;
+    ctx.set("component", &component.id);
+    ctx.set("install_dir", &path.to_string_lossy());
+    let result = ctx.activate(component);
+    ctx.finish(result, &component.name)
+}
+
+// Deletes a side by side install other than the active one, along with its plugins
+pub fn delete_install(component: &str, path: &Path) -> Result<()> {
+    let registry = Registry::load()?;
+
+    if registry.active.get(component).is_some_and(|p| p == path) {
+        return Err(eyre!(
+            "{} is the active install, switch to another one before deleting it.",
+            path.display()
+        ));
+    }
+
+    if registry
+        .installs_of(component)
+        .any(|i| i.path.as_deref() == Some(path))
+    {
+        uninstall(component, Some(path), false)
+    } else {
+        Ok(file::remove(path)?)
+    }
+}
+
+fn side_by_side(component: &Component) -> Result<&SideBySide> {
+    component
+        .side_by_side
+        .as_ref()
+        .ok_or_else(|| eyre!("{} doesn't keep versions side by side.", component.name))
+}
 
-        // Download & extract OBS config
-        let cfg_path = exe_dir.join("daw-obs-config-master.zip");
-        let cfg_name = exe_dir.join("daw-obs-config-master");
-        let cfg_src = cfg_name.join("obs-studio");
-        let cfg_dst = cfg_dir.join("obs-studio");
+// Files in `from`, and how many of them are already in `to`, if `from` exists yet
+fn count_files(from: &Path, to: &Path) -> Option<(usize, usize)> {
+    let mut counts = (0, 0);
 
-        if cfg_path.exists() {
-            fs::remove_file(&cfg_path)?;
+    for entry in fs::read_dir(from).ok()? {
+        let entry = entry.ok()?;
+        let dst_path = to.join(entry.file_name());
+
+        if entry.file_type().ok()?.is_dir() {
+            let (files, existing) = count_files(&entry.path(), &dst_path).unwrap_or_default();
+            counts = (counts.0 + files, counts.1 + existing);
+        } else {
+            counts = (counts.0 + 1, counts.1 + dst_path.is_file() as usize);
         }
-        if cfg_name.exists() {
-            fs::remove_dir_all(&cfg_name)?;
+    }
+
+    Some(counts)
+}
+
+// The version in an install folder's name, e.g. "31.0.2" from "OBS-Studio-31.0.2-Windows"
+fn version_in_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.split(['-', '_', ' '])
+        .find(|part| part.parse::<Version>().is_ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// Downloads from the component's mirrors first and then `url`, retrying as configured
+fn download(component: &str, url: &str, path: &Path, tx: &Sender<Event>) -> Result<()> {
+    let config = Config::load()?;
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let urls = config.download_urls(component, &file_name, url);
+    file::download_retry(&urls, path, tx, config.retry())
+}
+
+// Downloads an asset unless already present, then checks it against its published digest
+fn download_asset(
+    component: &str,
+    release: &Release,
+    asset: &Asset,
+    path: &Path,
+    tx: &Sender<Event>,
+) -> Result<()> {
+    if !path.exists() {
+        download(component, &asset.url, path, tx)?;
+    }
+
+    if let Some(sha256) = release.sha256(asset) {
+        file::verify_sha256(path, &sha256)?;
+    }
+
+    Ok(())
+}
+
+// The name an asset is downloaded under: the one the manifest picked, else the last segment of
+// its URL rather than its name, which some sources let the uploader set to anything, such as a
+// GitLab link title. Anything but a plain file name is refused so the download can't land
+// outside the executable's folder.
+fn asset_file_name(asset: &Asset) -> Result<String> {
+    let name = match &asset.file_name {
+        Some(name) => name.clone(),
+        None => {
+            let url = Url::parse(&asset.url)
+                .map_err(|e| eyre!("Invalid asset URL '{}': {}", asset.url, e))?;
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_default()
+                .to_string()
         }
+    };
+
+    let mut components = Path::new(&name).components();
+    match (components.next(), components.next()) {
+        (Some(path::Component::Normal(_)), None) => Ok(name),
+        _ => Err(eyre!(
+            "Asset {} has no usable file name, '{}' from {}.",
+            asset.name,
+            name,
+            asset.url
+        )),
+    }
+}
+
+// Extracts an archive with the configured options
+fn extract(
+    path: impl AsRef<Path>,
+    dir: impl AsRef<Path>,
+    tx: &Sender<Event>,
+) -> Result<Vec<PathBuf>> {
+    let options = Config::load()?.extract_options();
+    archive::extract(path.as_ref(), dir.as_ref(), options, tx)
+}
+
+// User-local folder for installs which can't live next to the executable without root,
+// following the XDG base directory spec on Linux. It's created here rather than by a step, so
+// no install records it and removing one never removes the folder the others live in.
+fn data_dir(exe_dir: &Path) -> Result<PathBuf> {
+    if !cfg!(target_os = "linux") {
+        return Ok(exe_dir.to_path_buf());
+    }
+
+    let data_home = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME")?).join(".local/share"),
+    };
+
+    let data_dir = data_home.join("obs-install-manager");
+    fs::create_dir_all(&data_dir)?;
+    Ok(data_dir)
+}
+
+// An explicit version wins, otherwise the newest release on the component's configured
+// channel which matches its configured version requirement
+fn resolve(
+    source: &dyn ReleaseSource,
+    component: &str,
+    version: Option<String>,
+) -> Result<Release> {
+    let config = Config::load()?;
+    let req = config.version_req(component)?;
+
+    match version {
+        Some(version) => source.release(Some(&version)),
+        None => source.select(config.channel(component), req.as_ref()),
+    }
+}
+
+// Variables every step can use
+fn base_vars() -> Result<HashMap<String, String>> {
+    let exe_dir = file::exe_dir()?;
+    let home = std::env::home_dir().unwrap_or_default();
+
+    Ok([
+        ("data_dir", data_dir(&exe_dir)?),
+        ("exe_dir", exe_dir),
+        ("home", home),
+        ("os", std::env::consts::OS.into()),
+        ("arch", std::env::consts::ARCH.into()),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string_lossy().into_owned()))
+    .collect())
+}
+
+// Replaces known `{name}` variables, leaving any other braces, as in shell scripts, alone
+fn expand(s: &str, vars: &HashMap<String, String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = s;
 
-        file::download(&crate::OBS_CONFIG_URL.to_string(), &cfg_path, &tx)?;
-        file::extract_zip(&cfg_path, &exe_dir.to_path_buf())?;
-        file::copy_dir(&cfg_src, &cfg_dst)?;
-        fs::remove_file(&cfg_path)?;
-        fs::remove_dir_all(&cfg_name)?;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let tail = &rest[start + 1..];
 
-        // OBS ASIO plugin
+        match tail
+            .find('}')
+            .and_then(|end| Some((vars.get(&tail[..end])?, end)))
         {
-            // Get latest asset infos
-            let git_release = github_api_client.get_release(&crate::OBS_ASIO_REPO, None)?;
-            let git_assets = git_release.get_assets(Some(vec!["zip"]), None, None);
-            let git_asset = git_assets.first().ok_or_eyre("Git asset not found!")?;
+            Some((value, end)) => {
+                expanded.push_str(value);
+                rest = &tail[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = tail;
+            }
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+// Relative paths are relative to the executable's folder
+fn expand_path(s: &str, vars: &HashMap<String, String>) -> PathBuf {
+    Path::new(&vars["exe_dir"]).join(expand(s, vars))
+}
+
+// Runs a component's manifest steps
+struct Context<'a> {
+    manifest: &'a Manifest,
+    tx: &'a Sender<Event>,
+    vars: HashMap<String, String>,
+    // Components being installed, outermost first, to catch manifests which loop
+    chain: Vec<String>,
+    // What the current component has created, and the digest of its download
+    files: Vec<PathBuf>,
+    sha256: Option<String>,
+    // Undoes every component's steps if one fails
+    transaction: Transaction,
+    // Describes the steps in `plan` instead of running them
+    dry_run: bool,
+    plan: Vec<String>,
+    // Paths the plan creates (true) or deletes (false), latest last, so later steps see them
+    planned: Vec<(PathBuf, bool)>,
+    // Release tag per component id, resolved once and kept for every later install of it
+    versions: HashMap<String, String>,
+}
+
+impl<'a> Context<'a> {
+    fn new(manifest: &'a Manifest, tx: &'a Sender<Event>) -> Result<Self> {
+        let vars = base_vars()?;
 
-            // Download asset
-            let asset_path = exe_dir.join(&git_asset.name);
-            if !asset_path.exists() {
-                file::download(&git_asset.browser_download_url, &asset_path, &tx)?;
+        Ok(Self {
+            manifest,
+            tx,
+            vars,
+            chain: Vec::new(),
+            files: Vec::new(),
+            sha256: None,
+            transaction: Transaction::begin()?,
+            dry_run: false,
+            plan: Vec::new(),
+            planned: Vec::new(),
+            versions: HashMap::new(),
+        })
+    }
+
+    // Keeps the changes if everything succeeded, otherwise puts back what was there before
+    fn finish(self, result: Result<()>, name: &str) -> Result<()> {
+        match result {
+            Ok(()) => {
+                self.transaction.commit();
+                Ok(())
             }
+            Err(e) => match self.transaction.rollback() {
+                Ok(()) => Err(e.wrap_err(format!("{} failed, its changes were undone", name))),
+                Err(undo_err) => Err(e.wrap_err(format!(
+                    "{} failed, and undoing its changes did too: {}",
+                    name, undo_err
+                ))),
+            },
+        }
+    }
 
-            // Extract zip
-            file::extract_zip(&asset_path, &asset_dir)?;
-            fs::remove_file(&asset_path)?;
+    fn install(&mut self, component: &Component, version: Option<String>) -> Result<()> {
+        if self.chain.contains(&component.id) {
+            return Err(eyre!(
+                "Component '{}' installs itself via {}.",
+                component.id,
+                self.chain.join(" -> ")
+            ));
         }
 
-        // OBS atkAudio plugin
-        {
-            // Get latest asset infos
-            let git_release = github_api_client.get_release(&crate::OBS_ATK_REPO, None)?;
-            let git_assets = git_release.get_assets(Some(vec!["zip"]), None, None);
-            let git_asset = git_assets.first().ok_or_eyre("Git asset not found!")?;
-
-            // Download asset
-            let asset_path = exe_dir.join(&git_asset.name);
-            if !asset_path.exists() {
-                file::download(&git_asset.browser_download_url, &asset_path, &tx)?;
-            }
-
-            // Extract zip into sub folder
-            let asset_dir = exe_dir.join("atk_audio");
-            file::extract_zip(&asset_path, &asset_dir)?;
-            fs::remove_file(&asset_path)?;
-
-            // Filter entries for platform and extract zip
-            for entry in fs::read_dir(&asset_dir)? {
-                let entry_path = entry?.path();
-                let entry_name = entry_path.to_str().unwrap().to_lowercase();
-                if entry_name.contains("windows") && entry_name.contains("zip") {
-                    file::extract_zip(&entry_path, &asset_dir)?;
-                }
+        // Resolve the release up front so every step can use its variables
+        let version = version.or_else(|| self.versions.get(&component.id).cloned());
+        let release = match &component.source {
+            Some(source) => {
+                let release = resolve(source.release_source()?.as_ref(), &component.id, version)?;
+                self.versions
+                    .insert(component.id.clone(), release.tag.clone());
+                let rules = AssetRules::host()
+                    .ext(&component.assets.ext)
+                    .exclude(&component.assets.exclude);
+                let asset = rules.select(&release.assets)?.clone();
+                Some((release, asset))
             }
+            None => None,
+        };
+        let asset_file = release
+            .as_ref()
+            .map(|(_, asset)| asset_file_name(asset))
+            .transpose()?;
+
+        // A nested component's variables and files don't leak back into this one
+        let saved = (
+            self.vars.clone(),
+            std::mem::take(&mut self.files),
+            self.sha256.take(),
+        );
+        let parent = self.chain.last().cloned();
+        let tag = release
+            .as_ref()
+            .map(|(r, _)| r.tag.as_str())
+            .unwrap_or_default();
+        self.note(
+            format!("Install {} {}", component.name, tag)
+                .trim_end()
+                .into(),
+        );
+        self.chain.push(component.id.clone());
+        self.set("component", &component.id);
 
-            fs::remove_dir_all(&asset_dir)?;
+        if let (Some((release, asset)), Some(asset_file)) = (&release, &asset_file) {
+            let asset_path = self.path("{exe_dir}").join(asset_file);
+            let asset_stem = asset_path.file_stem().unwrap_or_default().to_string_lossy();
+            self.set("version", &release.tag);
+            self.set("asset", &asset.name);
+            self.set("asset_stem", &asset_stem);
+            self.set("asset_path", &asset_path.to_string_lossy());
         }
 
-        // Create OBS shortcut
-        {
-            let scut_path = exe_dir.join("OBS.lnk");
-            if scut_path.exists() {
-                fs::remove_file(&scut_path)?;
+        let result = component
+            .steps
+            .iter()
+            .try_for_each(|step| self.run(component, release.as_ref(), step))
+            .and_then(|_| {
+                // Nothing to record for components which only open a web page or the like
+                if self.dry_run || (release.is_none() && self.files.is_empty()) {
+                    return Ok(());
+                }
+
+                let mut registry = Registry::load()?;
+                registry.record(Install {
+                    component: component.id.clone(),
+                    version: release
+                        .as_ref()
+                        .map(|(r, _)| r.tag.clone())
+                        .unwrap_or_default(),
+                    path: self.vars.get("install_dir").map(PathBuf::from),
+                    files: self.files.clone(),
+                    source_url: release.as_ref().map(|(_, a)| a.url.clone()),
+                    sha256: self.sha256.clone(),
+                    installed_at: Install::now(),
+                    parent,
+                    shared: component.shared,
+                });
+                registry.save()
+            });
+
+        self.chain.pop();
+        (self.vars, self.files, self.sha256) = saved;
+        result
+    }
+
+    fn run(
+        &mut self,
+        component: &Component,
+        release: Option<&(Release, Asset)>,
+        step: &Step,
+    ) -> Result<()> {
+        if self.dry_run {
+            return self.describe(component, release, step);
+        }
+
+        match step {
+            Step::Set { name, value } => {
+                let value = self.expand(value);
+                self.set(name, &value);
+            }
+            // Downloads are kept when undoing, so trying again doesn't fetch them twice
+            Step::Download => {
+                let (release, asset) = release
+                    .ok_or_else(|| eyre!("{} has no source to download from.", component.name))?;
+                let path = self.path("{asset_path}");
+                download_asset(&component.id, release, asset, &path, self.tx)?;
+                self.sha256 = Some(file::sha256(&path)?);
+                self.created([path]);
+            }
+            // Files an archive overwrites inside an existing folder keep their new contents when
+            // undoing, it's the folders and files it adds which are removed
+            Step::Extract { from, to } => {
+                let to = self.path(to);
+                self.transaction.create(&to);
+                let created = extract(self.path(from), &to, self.tx)?;
+                self.transaction.created(&created);
+                self.created(created);
+            }
+            Step::ExtractNested { dir, matching, to } => {
+                let to = self.path(to);
+                for entry in fs::read_dir(self.path(dir))? {
+                    let entry_path = entry?.path();
+                    let entry_name = entry_path.to_string_lossy().to_lowercase();
+                    if matching
+                        .iter()
+                        .all(|m| entry_name.contains(&m.to_lowercase()))
+                    {
+                        self.transaction.create(&to);
+                        let created = extract(&entry_path, &to, self.tx)?;
+                        self.transaction.created(&created);
+                        self.created(created);
+                    }
+                }
+            }
+            // Whatever an installer does is up to it, and can't be undone
+            Step::Run { path } => {
+                file::run(self.path(path))?;
+            }
+            Step::Remove { path } => {
+                let path = self.path(path);
+                self.transaction.set_aside(&path)?;
+                self.files.retain(|f| !f.starts_with(&path));
+            }
+            Step::CreateDir { path } => {
+                let path = self.path(path);
+                if !path.exists() {
+                    self.transaction.create(&path);
+                    fs::create_dir_all(&path)?;
+                    self.created([path]);
+                }
+            }
+            Step::WriteFile {
+                path,
+                contents,
+                executable,
+            } => {
+                let path = self.path(path);
+                self.transaction.set_aside(&path)?;
+                fs::write(&path, self.expand(contents))?;
+
+                #[cfg(unix)]
+                if *executable {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+                }
+                #[cfg(not(unix))]
+                let _ = executable;
+
+                self.created([path]);
+            }
+            Step::Symlink { target, link } => {
+                let link = self.path(link);
+                self.transaction.set_aside(&link)?;
+                file::symlink_dir(self.path(target), &link)?;
+                self.created([link]);
+            }
+            Step::CopyDir { from, to } => {
+                let (from, to) = (self.path(from), self.path(to));
+                self.transaction.create(&to);
+                self.transaction.preserve(&from, &to)?;
+                let created = file::copy_dir(from, to, "Copying", self.tx)?;
+                self.transaction.created(&created);
+                self.created(created);
+            }
+            Step::Shortcut { link, target } => {
+                #[cfg(windows)]
+                {
+                    let link = self.path(link);
+                    self.transaction.set_aside(&link)?;
+                    scut::create_shortcut(&link, &self.path(target))?;
+                    self.created([link]);
+                }
+                #[cfg(not(windows))]
+                return Err(eyre!(
+                    "Shortcut {} -> {} needs Windows.",
+                    self.expand(link),
+                    self.expand(target)
+                ));
+            }
+            // An app already in /Applications is set aside, and put back if the install fails
+            Step::InstallDmg { path, volume } => {
+                let dmg_path = self.path(path);
+                let transaction = &mut self.transaction;
+                let app = file::install_dmg(&dmg_path.to_string_lossy(), volume, |app| {
+                    transaction.set_aside(app)
+                })?;
+                self.created([app]);
+            }
+            Step::Open { path } => opener::open(self.path(path))?,
+            Step::OpenUrl { url } => opener::open_browser(self.expand(url))?,
+            Step::Install { component } => {
+                let manifest = self.manifest;
+                let component = manifest.component(component)?;
+                self.install(component, None)?;
             }
-            let target_path = asset_dir.join("bin/64bit/obs64.exe");
-            scut::create_shortcut(scut_path, target_path)?;
+            Step::Activate => self.activate(component)?,
         }
 
-        // Open exe directory
-        opener::open(exe_dir)?;
+        Ok(())
     }
 
-    // MacOS setup
-    #[cfg(target_os = "macos")]
-    {
-        // Install DMG
-        file::install_dmg(&asset_path.to_str().unwrap(), "OBS")?;
-        fs::remove_file(&asset_path)?;
-
-        // Get home path from env variable
-        let home = std::env::var("HOME")?;
-        let home = std::path::PathBuf::from(&home);
-
-        // Download & extract OBS config
-        let cfg_path = exe_dir.join("daw-obs-config-macos-master.zip");
-        let cfg_name = exe_dir.join("daw-obs-config-macos-master");
-        let cfg_src = cfg_name.join("obs-studio");
-        let cfg_dst = home.join("Library/Application Support/obs-studio");
-
-        if cfg_path.exists() {
-            fs::remove_file(&cfg_path)?;
+    fn activate(&mut self, component: &Component) -> Result<()> {
+        let side_by_side = side_by_side(component)?;
+        let install_dir = self
+            .vars
+            .get("install_dir")
+            .map(PathBuf::from)
+            .ok_or_else(|| eyre!("{} has no {{install_dir}} to activate.", component.name))?;
+
+        let start = self.files.len();
+
+        for step in &side_by_side.activate {
+            if let Step::Activate = step {
+                return Err(eyre!("{} activates itself.", component.name));
+            }
+            self.run(component, None, step)?;
         }
-        if cfg_name.exists() {
-            fs::remove_dir_all(&cfg_name)?;
+
+        // The shortcut or launcher link now belongs to this install, so deleting another one
+        // leaves it be
+        let activated = self.files[start.min(self.files.len())..].to_vec();
+        let mut registry = Registry::load()?;
+
+        for install in registry.installs.iter_mut() {
+            if install.component != component.id {
+                continue;
+            }
+            if install.path.as_ref() == Some(&install_dir) {
+                let new: Vec<PathBuf> = activated
+                    .iter()
+                    .filter(|f| !install.files.contains(f))
+                    .cloned()
+                    .collect();
+                install.files.extend(new);
+            } else {
+                install.files.retain(|f| !activated.contains(f));
+            }
         }
 
-        file::download(&crate::OBS_CONFIG_URL.to_string(), &cfg_path, &tx)?;
-        file::extract_zip(&cfg_path, &exe_dir.to_path_buf())?;
-        file::copy_dir(&cfg_src, &cfg_dst)?;
-        fs::remove_file(&cfg_path)?;
-        fs::remove_dir_all(&cfg_name)?;
+        registry.active.insert(component.id.clone(), install_dir);
+        registry.save()
     }
 
-    Ok(())
-}
+    // Adds a step to the plan in words, running only those which decide what later ones do
+    fn describe(
+        &mut self,
+        component: &Component,
+        release: Option<&(Release, Asset)>,
+        step: &Step,
+    ) -> Result<()> {
+        let action = match step {
+            Step::Set { name, value } => {
+                let value = self.expand(value);
+                self.set(name, &value);
+                return Ok(());
+            }
+            Step::Install { component } => {
+                let manifest = self.manifest;
+                let component = manifest.component(component)?;
+                return self.install(component, None);
+            }
+            Step::Download => {
+                let (_, asset) = release
+                    .ok_or_else(|| eyre!("{} has no source to download from.", component.name))?;
+                let path = self.path("{asset_path}");
+                let exists = self.exists(&path);
+                self.planned.push((path.clone(), true));
+                match exists {
+                    true => format!("Use the already downloaded {}", path.display()),
+                    false if asset.size > 0 => format!(
+                        "Download {} ({}) to {}",
+                        asset.url,
+                        format_bytes(asset.size),
+                        path.display()
+                    ),
+                    false => format!("Download {} to {}", asset.url, path.display()),
+                }
+            }
+            Step::Extract { from, to } => {
+                let to = self.path(to);
+                self.planned.push((to.clone(), true));
+                format!(
+                    "Extract {} into {}",
+                    self.path(from).display(),
+                    to.display()
+                )
+            }
+            Step::ExtractNested { dir, matching, to } => {
+                let to = self.path(to);
+                self.planned.push((to.clone(), true));
+                format!(
+                    "Extract the archives in {} named with {} into {}",
+                    self.path(dir).display(),
+                    matching.join(", "),
+                    to.display()
+                )
+            }
+            Step::Run { path } => format!("Run {}", self.path(path).display()),
+            Step::Remove { path } => {
+                let path = self.path(path);
+                if !self.exists(&path) {
+                    return Ok(());
+                }
+                let is_dir = path.symlink_metadata().is_ok_and(|m| m.is_dir());
+                self.planned.push((path.clone(), false));
+                match is_dir {
+                    true => format!("Delete the folder {}", path.display()),
+                    false => format!("Delete {}", path.display()),
+                }
+            }
+            Step::CreateDir { path } => {
+                let path = self.path(path);
+                if self.exists(&path) {
+                    return Ok(());
+                }
+                self.planned.push((path.clone(), true));
+                format!("Create the folder {}", path.display())
+            }
+            Step::WriteFile { path, .. } => {
+                let path = self.path(path);
+                let action = self.replace_or_create(&path);
+                format!("{} {}", action, path.display())
+            }
+            Step::Symlink { target, link } => {
+                let link = self.path(link);
+                format!(
+                    "{} the link {} -> {}",
+                    self.replace_or_create(&link),
+                    link.display(),
+                    self.path(target).display()
+                )
+            }
+            Step::CopyDir { from, to } => {
+                let (from, to) = (self.path(from), self.path(to));
+                match count_files(&from, &to) {
+                    Some((0, _)) | None => {
+                        format!("Copy the folder {} to {}", from.display(), to.display())
+                    }
+                    Some((files, 0)) => {
+                        format!(
+                            "Copy {} files from {} to {}",
+                            files,
+                            from.display(),
+                            to.display()
+                        )
+                    }
+                    Some((files, existing)) => format!(
+                        "Copy {} files from {} to {}, overwriting {}",
+                        files,
+                        from.display(),
+                        to.display(),
+                        existing
+                    ),
+                }
+            }
+            Step::Shortcut { link, target } => {
+                let link = self.path(link);
+                format!(
+                    "{} the shortcut {} -> {}",
+                    self.replace_or_create(&link),
+                    link.display(),
+                    self.path(target).display()
+                )
+            }
+            Step::InstallDmg { path, .. } => format!(
+                "Copy the app in {} to /Applications, replacing one already there",
+                self.path(path).display()
+            ),
+            Step::Open { path } => format!("Open {}", self.path(path).display()),
+            Step::OpenUrl { url } => format!("Open {} in the browser", self.expand(url)),
+            Step::Activate => {
+                let side_by_side = side_by_side(component)?;
+                for step in &side_by_side.activate {
+                    self.describe(component, release, step)?;
+                }
+                format!(
+                    "Make {} the active version",
+                    self.path("{install_dir}").display()
+                )
+            }
+        };
 
-// Kilohearts Bundle
-pub fn khs(tx: Sender<Event>) -> Result<()> {
-    // Build paths
-    let exe_path = std::env::current_exe()?;
-    let exe_dir = exe_path.parent().unwrap();
-    #[cfg(any(target_os = "windows", target_os = "linux"))]
-    let file_path = exe_dir.join("kilohearts_installer.exe");
-    #[cfg(target_os = "macos")]
-    let file_path = exe_dir.join("kilohearts_installer.dmg");
+        self.note(action);
+        Ok(())
+    }
 
-    // Download & run
-    if !file_path.exists() {
-        file::download(&crate::KHS_URL.to_string(), &file_path, &tx)?;
+    // Whether a path will be there once the steps planned so far have run
+    fn exists(&self, path: &Path) -> bool {
+        let planned = self
+            .planned
+            .iter()
+            .rev()
+            .find_map(|(p, created)| match created {
+                true => (path == p).then_some(true),
+                false => path.starts_with(p).then_some(false),
+            });
+        planned.unwrap_or_else(|| path.symlink_metadata().is_ok())
     }
-    file::run(&file_path)?;
-    fs::remove_file(&file_path)?;
 
-    Ok(())
-}
+    fn replace_or_create(&mut self, path: &Path) -> &'static str {
+        let exists = self.exists(path);
+        self.planned.push((path.into(), true));
+        match exists {
+            true => "Replace",
+            false => "Create",
+        }
+    }
 
-// ReaPlugs Bundle
-#[cfg(any(target_os = "windows", target_os = "linux"))]
-pub fn rea(tx: Sender<Event>) -> Result<()> {
-    // Build Paths
-    let exe_path = std::env::current_exe()?;
-    let exe_dir = exe_path.parent().unwrap();
-    let file_path = exe_dir.join("reaplugs_installer.exe");
+    // Adds an action to the plan, indented under the component it installs
+    fn note(&mut self, action: String) {
+        if self.dry_run {
+            self.plan
+                .push(format!("{}{}", "  ".repeat(self.chain.len()), action));
+        }
+    }
 
-    // Download & run
-    if !file_path.exists() {
-        file::download(&crate::REA_URL.to_string(), &file_path, &tx)?;
+    // Notes created paths, skipping those inside a folder already noted
+    fn created(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            if !self.files.iter().any(|f| path.starts_with(f)) {
+                self.files.push(path);
+            }
+        }
     }
-    file::run(&file_path)?;
-    fs::remove_file(&file_path)?;
 
-    Ok(())
-}
+    fn set(&mut self, name: &str, value: &str) {
+        self.vars.insert(name.into(), value.into());
+    }
 
-// Voicemeeter Banana
-#[cfg(target_os = "windows")]
-pub fn vmb(tx: Sender<Event>) -> Result<()> {
-    // Build paths
-    let exe_path = std::env::current_exe()?;
-    let exe_dir = exe_path.parent().unwrap();
-    let zip_path = exe_dir.join("voicemeeter_banana_installer.zip");
+    fn expand(&self, s: &str) -> String {
+        expand(s, &self.vars)
+    }
 
-    // Download zip
-    if !zip_path.exists() {
-        file::download(&crate::VMB_URL.to_string(), &zip_path, &tx)?;
+    fn path(&self, s: &str) -> PathBuf {
+        expand_path(s, &self.vars)
     }
+}
 
-    // Extract zip
-    file::extract_zip(&zip_path, &exe_dir.to_path_buf())?;
-    fs::remove_file(&zip_path)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Run installer
-    let file_path = exe_dir.join("voicemeeterprosetup.exe");
-    file::run(&file_path)?;
-    fs::remove_file(&file_path)?;
+    fn asset(name: &str, url: &str) -> Asset {
+        Asset {
+            name: name.into(),
+            url: url.into(),
+            size: 0,
+            sha256: None,
+            file_name: None,
+        }
+    }
 
-    Ok(())
-}
+    #[test]
+    fn asset_file_name_comes_from_the_url() {
+        let link = asset(
+            "../Windows build/latest",
+            "https://gitlab.com/a/b/-/releases/v1/downloads/plugin-windows.zip",
+        );
+        assert_eq!(asset_file_name(&link).unwrap(), "plugin-windows.zip");
 
-// BlackHole
-#[cfg(target_os = "macos")]
-pub fn eab(_: Sender<Event>) -> Result<()> {
-    opener::open_browser(&crate::EAB_URL)?;
-    Ok(())
-}
+        for url in [
+            "https://example.com/",
+            "https://example.com/a/..",
+            "https://example.com/a/%2E%2E",
+            "file:///",
+        ] {
+            assert!(
+                asset_file_name(&asset("plugin.zip", url)).is_err(),
+                "{}",
+                url
+            );
+        }
+    }
 
-// Sonobus
-#[cfg(any(target_os = "windows", target_os = "macos"))]
-pub fn sbs(tx: Sender<Event>) -> Result<()> {
-    // Search tags per operating system
-    #[cfg(target_os = "windows")]
-    let incl = vec!["win", "exe"];
-    #[cfg(target_os = "macos")]
-    let incl = vec!["mac", "dmg"];
+    #[test]
+    fn static_sources_download_under_their_file_name() {
+        let source = StaticSource {
+            url: "https://example.com/download/win".into(),
+            file_name: "kilohearts_installer.exe".into(),
+            page: None,
+            pattern: None,
+        };
+        let release = source.release(None).unwrap();
+        let file_name = asset_file_name(&release.assets[0]).unwrap();
+        let asset_path = Path::new("/opt/manager").join(file_name);
+        assert!(asset_path.ends_with("kilohearts_installer.exe"));
 
-    // Get latest OBS asset infos
-    let git_release = GithubApiClient::new()?.get_release(&crate::SONOBUS_REPO, None)?;
-    let git_assets = git_release.get_assets(Some(incl), None, None);
-    let git_asset = git_assets.first().ok_or_eyre("Git asset not found!")?;
+        let escaping = StaticSource {
+            file_name: "../installer.exe".into(),
+            ..source
+        };
+        let release = escaping.release(None).unwrap();
+        assert!(asset_file_name(&release.assets[0]).is_err());
+    }
 
-    // Build paths
-    let exe_path = std::env::current_exe()?;
-    let exe_dir = exe_path.parent().unwrap();
-    let file_path = exe_dir.join(&git_asset.name);
+    fn install(component: &str, files: Vec<PathBuf>) -> Install {
+        Install {
+            component: component.into(),
+            files,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn uninstall_removes_recorded_files() {
+        let dir =
+            std::env::temp_dir().join(format!("obs-install-manager-rm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("plugin")).unwrap();
+        fs::write(dir.join("plugin/plugin.dll"), "dll").unwrap();
+        fs::write(dir.join("keep.txt"), "keep").unwrap();
 
-    // Download & run
-    if !file_path.exists() {
-        file::download(&git_asset.browser_download_url, &file_path, &tx)?;
+        let mut registry = Registry {
+            installs: vec![install("plugin", vec![dir.join("plugin")])],
+            ..Default::default()
+        };
+        remove_installs(&mut registry, "plugin", None, false).unwrap();
+        assert!(registry.installs.is_empty());
+        assert!(!dir.join("plugin").exists());
+        assert!(dir.join("keep.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
     }
-    file::run(&file_path)?;
-    fs::remove_file(&file_path)?;
 
-    Ok(())
+    #[test]
+    fn uninstall_fails_when_there_is_nothing_to_remove() {
+        let mut registry = Registry {
+            installs: vec![install("kilohearts", Vec::new())],
+            ..Default::default()
+        };
+
+        let err = remove_installs(&mut registry, "obs", None, false).unwrap_err();
+        assert_eq!(err.to_string(), "obs is not installed.");
+
+        let err = remove_installs(&mut registry, "kilohearts", None, false).unwrap_err();
+        assert!(err.to_string().contains("its own installer"), "{}", err);
+    }
 }
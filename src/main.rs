@@ -1,65 +1,36 @@
-use crate::{
-    app::{App, Result},
-    git::GithubRepo,
-};
+use crate::app::{App, Result};
+use clap::Parser;
 
 pub mod app;
+pub mod archive;
+pub mod asset;
+pub mod cache;
+pub mod cli;
+pub mod config;
 pub mod file;
 pub mod git;
 pub mod install;
+pub mod manifest;
+pub mod registry;
+pub mod source;
+pub mod transaction;
 pub mod ui;
+pub mod version;
 
 #[cfg(windows)]
 pub mod scut;
 
-pub const OBS_REPO: GithubRepo = GithubRepo {
-    author: "obsproject",
-    name: "obs-studio",
-};
-
-pub const OBS_ASIO_REPO: GithubRepo = GithubRepo {
-    author: "andersama",
-    name: "obs-asio",
-};
-
-pub const OBS_ATK_REPO: GithubRepo = GithubRepo {
-    author: "atkAudio",
-    name: "PluginForObsRelease",
-};
-
-pub const BLACKHOLE_REPO: GithubRepo = GithubRepo {
-    author: "ExistentialAudio",
-    name: "BlackHole",
-};
-
-pub const SONOBUS_REPO: GithubRepo = GithubRepo {
-    author: "sonosaurus",
-    name: "sonobus",
-};
-
-#[cfg(any(target_os = "windows", target_os = "linux"))]
-pub const OBS_CONFIG_URL: &str =
-    "https://github.com/robertwaredev/daw-obs-config/archive/refs/heads/master.zip";
-
-#[cfg(target_os = "macos")]
-pub const OBS_CONFIG_URL: &str =
-    "https://github.com/robertwaredev/daw-obs-config-macos/archive/refs/heads/master.zip";
-
-pub const VMB_URL: &str = "https://download.vb-audio.com/Download_CABLE/VoicemeeterSetup_v2119.zip";
-
-pub const REA_URL: &str = "https://www.reaper.fm/reaplugs/reaplugs236_x64-install.exe";
-
-#[cfg(any(target_os = "windows", target_os = "linux"))]
-pub const KHS_URL: &str = "https://kilohearts.com/data/install/_/win";
-#[cfg(target_os = "macos")]
-pub const KHS_URL: &str = "https://kilohearts.com/data/install/_/mac";
-
-pub const EAB_URL: &str = "https://existential.audio/blackhole/";
-
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let args = cli::Args::parse();
+
+    // Commands run without the menu, for scripts and remote shells
+    if let Some(command) = args.command {
+        std::process::exit(cli::run(command, args.json));
+    }
+
     let term = ratatui::init();
-    let res = App::new().run(term);
+    let res = App::new().and_then(|mut app| app.run(term));
     ratatui::restore();
     res
 }
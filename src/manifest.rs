@@ -0,0 +1,215 @@
+use crate::{
+    asset::AssetRules,
+    file,
+    git::GithubRepo,
+    source::{GiteaSource, GithubSource, GitlabSource, ReleaseSource, StaticSource},
+};
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+pub const MANIFEST_FILE: &str = "components.json";
+
+// Built in components, which a manifest next to the executable replaces or adds to by id
+const DEFAULT_MANIFEST: &str = include_str!("components.json");
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub components: Vec<Component>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Component {
+    pub id: String,
+    // Shown in the menu as "Install <name>"
+    pub name: String,
+    // Operating systems as named by `std::env::consts::OS`, every one if empty. A component can
+    // be listed once per platform under the same id.
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    // Left out of the menu, for components only installed by another's steps
+    #[serde(default)]
+    pub hidden: bool,
+    // Used by every install of its parent, like the OBS config, and kept when the parent is
+    // uninstalled unless asked
+    #[serde(default)]
+    pub shared: bool,
+    // Where releases come from, none for components which only run steps
+    #[serde(default)]
+    pub source: Option<Source>,
+    #[serde(default)]
+    pub assets: AssetRules,
+    pub steps: Vec<Step>,
+    // Folder holding the user's settings, which `config backup` archives
+    #[serde(default)]
+    pub config_dir: Option<String>,
+    // For components which keep every version in a folder of its own
+    #[serde(default)]
+    pub side_by_side: Option<SideBySide>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SideBySide {
+    // Folders holding installs, e.g. "{exe_dir}"
+    pub dirs: Vec<String>,
+    // File which marks a folder as an install, relative to it, e.g. "bin/64bit/obs64.exe"
+    pub marker: String,
+    // Steps making the install in {install_dir} the one which gets launched
+    pub activate: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Source {
+    Github(GithubRepo),
+    Gitlab(GitlabSource),
+    Gitea(GiteaSource),
+    Static(StaticSource),
+}
+
+// Install actions, run in order. Strings may use `{name}` variables: exe_dir, data_dir, home,
+// os, arch, component, and once a release is resolved version, asset, asset_path and
+// asset_stem. Relative paths are relative to the executable's folder.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    // Defines a variable for the following steps and the components they install
+    Set {
+        name: String,
+        value: String,
+    },
+    // Downloads the release asset to {asset_path} unless present, and checks its digest
+    Download,
+    Extract {
+        from: String,
+        to: String,
+    },
+    // Extracts the archives in `dir` whose names contain every one of `matching`
+    ExtractNested {
+        dir: String,
+        matching: Vec<String>,
+        to: String,
+    },
+    Run {
+        path: String,
+    },
+    Remove {
+        path: String,
+    },
+    CreateDir {
+        path: String,
+    },
+    WriteFile {
+        path: String,
+        #[serde(default)]
+        contents: String,
+        #[serde(default)]
+        executable: bool,
+    },
+    Symlink {
+        target: String,
+        link: String,
+    },
+    CopyDir {
+        from: String,
+        to: String,
+    },
+    // Windows .lnk shortcut, replacing any existing one
+    Shortcut {
+        link: String,
+        target: String,
+    },
+    // Copies the app out of a MacOS disk image into /Applications
+    InstallDmg {
+        path: String,
+        volume: String,
+    },
+    Open {
+        path: String,
+    },
+    OpenUrl {
+        url: String,
+    },
+    // Installs another component's configured version, which sees this one's variables
+    Install {
+        component: String,
+    },
+    // Runs the `side_by_side` activate steps and marks {install_dir} as the active install
+    Activate,
+}
+
+impl Source {
+    pub fn release_source(&self) -> Result<Box<dyn ReleaseSource>> {
+        Ok(match self {
+            Self::Github(repo) => Box::new(GithubSource::new(repo.clone())?),
+            Self::Gitlab(source) => Box::new(source.clone()),
+            Self::Gitea(source) => Box::new(source.clone()),
+            Self::Static(source) => Box::new(source.clone()),
+        })
+    }
+}
+
+impl Component {
+    pub fn supports_host(&self) -> bool {
+        self.platforms.is_empty() || self.platforms.iter().any(|p| p == std::env::consts::OS)
+    }
+}
+
+impl Manifest {
+    pub fn path() -> Result<PathBuf> {
+        Ok(file::exe_dir()?.join(MANIFEST_FILE))
+    }
+
+    // The built in components for this platform, with those from the manifest file in place
+    // of any sharing their id
+    pub fn load() -> Result<Self> {
+        let mut manifest =
+            Self::parse(DEFAULT_MANIFEST).map_err(|e| eyre!("Built in manifest error: {}", e))?;
+        let path = Self::path()?;
+
+        if path.exists() {
+            let json = fs::read_to_string(&path)?;
+            let overrides = Self::parse(&json)
+                .map_err(|e| eyre!("Manifest error in {}: {}", path.display(), e))?;
+
+            for component in overrides.components {
+                match manifest
+                    .components
+                    .iter_mut()
+                    .find(|c| c.id == component.id)
+                {
+                    Some(existing) => *existing = component,
+                    None => manifest.components.push(component),
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    // Keeps the first entry per id which supports this platform
+    fn parse(json: &str) -> Result<Self> {
+        let manifest = serde_json::from_str::<Self>(json)?;
+        let mut components: Vec<Component> = Vec::new();
+
+        for component in manifest.components {
+            if component.supports_host() && !components.iter().any(|c| c.id == component.id) {
+                components.push(component);
+            }
+        }
+
+        Ok(Self { components })
+    }
+
+    pub fn component(&self, id: &str) -> Result<&Component> {
+        self.components
+            .iter()
+            .find(|c| c.id == id)
+            .ok_or_else(|| eyre!("Component '{}' is not available on this platform.", id))
+    }
+
+    // Components listed in the menu
+    pub fn visible(&self) -> impl Iterator<Item = &Component> {
+        self.components.iter().filter(|c| !c.hidden)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::file;
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const REGISTRY_FILE: &str = "installed.json";
+
+// Everything the tool has installed and where, the source of truth for status, updates and
+// uninstall
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Registry {
+    pub installs: Vec<Install>,
+    // Folder of the install which gets launched, per component with side by side installs
+    pub active: HashMap<String, PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Install {
+    pub component: String,
+    // Release tag, empty for components without a source
+    pub version: String,
+    // Folder the component was installed into, if it has one
+    pub path: Option<PathBuf>,
+    // Files and folders the install created, a folder standing for everything in it
+    pub files: Vec<PathBuf>,
+    pub source_url: Option<String>,
+    // SHA-256 of the downloaded asset
+    pub sha256: Option<String>,
+    // Seconds since the Unix epoch
+    pub installed_at: u64,
+    // Component whose steps installed this one, if any
+    pub parent: Option<String>,
+    // Used by every install, like the OBS config, and kept on uninstall unless asked
+    pub shared: bool,
+}
+
+impl Install {
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+}
+
+impl Registry {
+    pub fn path() -> Result<PathBuf> {
+        Ok(file::exe_dir()?.join(REGISTRY_FILE))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let json = fs::read_to_string(&path)?;
+        serde_json::from_str::<Self>(&json)
+            .map_err(|e| eyre!("Registry error in {}: {}", path.display(), e))
+    }
+
+    // Written beside the registry and renamed over it, so it's never left half written
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    // Replaces any install of the same component into the same folder
+    pub fn record(&mut self, install: Install) {
+        self.installs
+            .retain(|i| i.component != install.component || i.path != install.path);
+        self.installs.push(install);
+    }
+
+    // Takes out the installs of `component`, or only the one into `path`, along with what their
+    // steps installed alongside them, parents first. Shared installs among the latter are left
+    // in unless `shared` is set.
+    pub fn take(&mut self, component: &str, path: Option<&Path>, shared: bool) -> Vec<Install> {
+        let (mut queue, rest): (Vec<Install>, Vec<Install>) = std::mem::take(&mut self.installs)
+            .into_iter()
+            .partition(|i| {
+                i.component == component && path.is_none_or(|p| i.path.as_deref() == Some(p))
+            });
+        self.installs = rest;
+
+        let mut taken = Vec::new();
+
+        while let Some(install) = queue.pop() {
+            let (children, rest): (Vec<Install>, Vec<Install>) = std::mem::take(&mut self.installs)
+                .into_iter()
+                .partition(|i| {
+                    i.parent.as_ref() == Some(&install.component)
+                        && i.path == install.path
+                        && (shared || !i.shared)
+                });
+            self.installs = rest;
+            queue.extend(children);
+            taken.push(install);
+        }
+
+        taken
+    }
+
+    pub fn installs_of<'a>(&'a self, component: &'a str) -> impl Iterator<Item = &'a Install> {
+        self.installs
+            .iter()
+            .filter(move |i| i.component == component)
+    }
+}
@@ -0,0 +1,550 @@
+use crate::{
+    git::{self, GithubApiClient, GithubRelease, GithubRepo},
+    version::{Version, VersionReq},
+};
+use color_eyre::{Result, eyre::eyre};
+use regex::Regex;
+use reqwest::{StatusCode, blocking::Client};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::fmt;
+use url::Url;
+
+pub const GITLAB_API_URL: &str = "https://gitlab.com/api/v4";
+pub const CODEBERG_API_URL: &str = "https://codeberg.org/api/v1";
+
+// Release info common to every source
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Release {
+    pub tag: String,
+    pub name: String,
+    pub published_at: String,
+    pub prerelease: bool,
+    pub draft: bool,
+    pub body: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub url: String,
+    pub size: u64,
+    // Lowercase hex SHA-256, when the source publishes one
+    pub sha256: Option<String>,
+    // The name to download it under when the manifest picks one, otherwise it's taken from the
+    // URL
+    #[serde(default)]
+    pub file_name: Option<String>,
+}
+
+impl Release {
+    // None for tags which aren't versions, like a static source's "latest"
+    pub fn version(&self) -> Option<Version> {
+        self.tag.parse().ok()
+    }
+
+    // The asset's own digest, else one listed right next to its exact name in the release
+    // notes, as in `<sha256>  OBS-Studio-31.0.2-Windows.zip` the way sha256sum prints it (with a
+    // `*` before binary files) or `OBS-Studio-31.0.2-Windows.zip: <sha256>`. A line naming
+    // `OBS-Studio-31.0.2-Windows.zip.sig` doesn't count.
+    pub fn sha256(&self, asset: &Asset) -> Option<String> {
+        if asset.sha256.is_some() {
+            return asset.sha256.clone();
+        }
+
+        let name = regex::escape(&asset.name);
+        let hash = "[0-9A-Fa-f]{64}";
+        let pattern = format!(
+            r"(?m)(?:^|[^0-9A-Za-z])({hash})[ \t*]+{name}(?:[ \t`|]|$)|(?:^|[ \t`|*]){name}(?::[ \t]*|[ \t*]+)({hash})(?:[^0-9A-Za-z]|$)"
+        );
+        let caps = Regex::new(&pattern).ok()?.captures(&self.body)?;
+
+        caps.get(1)
+            .or(caps.get(2))
+            .map(|hash| hash.as_str().to_lowercase())
+    }
+}
+
+pub trait ReleaseSource {
+    // Every release, newest first
+    fn releases(&self) -> Result<Vec<Release>>;
+
+    // The release tagged `version`, or the newest published stable release
+    fn release(&self, version: Option<&str>) -> Result<Release> {
+        let releases = self.releases()?;
+
+        let release = match version {
+            Some(version) => releases.into_iter().find(|r| r.tag == version),
+            None => newest(releases.into_iter().filter(|r| !r.draft && !r.prerelease)),
+        };
+
+        release.ok_or_else(|| eyre!("Release {} not found.", version.unwrap_or("latest")))
+    }
+
+    // The newest release on `channel` whose version matches `req`, if any
+    fn select(&self, channel: Channel, req: Option<&VersionReq>) -> Result<Release> {
+        let releases = self.releases()?.into_iter().filter(|r| {
+            channel.allows(r) && req.is_none_or(|req| r.version().is_some_and(|v| req.matches(&v)))
+        });
+
+        newest(releases).ok_or_else(|| eyre!("No {} release matches version requirement.", channel))
+    }
+}
+
+// Which releases a component may be updated to
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    // Betas and release candidates, as well as stable releases
+    Beta,
+    // Everything, including drafts
+    Any,
+}
+
+impl Channel {
+    pub fn allows(&self, release: &Release) -> bool {
+        // Some projects tag betas without flagging the release as a prerelease
+        let prerelease = release.prerelease || release.version().is_some_and(|v| v.is_prerelease());
+
+        match self {
+            Self::Stable => !release.draft && !prerelease,
+            Self::Beta => !release.draft,
+            Self::Any => true,
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Beta => write!(f, "beta"),
+            Self::Any => write!(f, "any"),
+        }
+    }
+}
+
+// Highest version wins, falling back to list order for tags which aren't versions
+pub fn newest(releases: impl Iterator<Item = Release>) -> Option<Release> {
+    let mut releases: Vec<Release> = releases.collect();
+    sort_releases(&mut releases);
+    releases.into_iter().next()
+}
+
+// Newest version first, keeping list order among equal or unparsable tags
+pub fn sort_releases(releases: &mut [Release]) {
+    releases.sort_by_cached_key(|r| std::cmp::Reverse(r.version()));
+}
+
+// GitHub
+pub struct GithubSource {
+    pub client: GithubApiClient,
+    pub repo: GithubRepo,
+}
+
+impl GithubSource {
+    pub fn new(repo: GithubRepo) -> Result<Self> {
+        Ok(Self {
+            client: GithubApiClient::new()?,
+            repo,
+        })
+    }
+}
+
+impl From<GithubRelease> for Release {
+    fn from(release: GithubRelease) -> Self {
+        Self {
+            tag: release.tag_name,
+            name: release.name,
+            published_at: release.published_at,
+            prerelease: release.prerelease,
+            draft: release.draft,
+            body: release.body,
+            assets: release
+                .assets
+                .into_iter()
+                .map(|a| Asset {
+                    sha256: a
+                        .digest
+                        .and_then(|d| Some(d.strip_prefix("sha256:")?.to_lowercase())),
+                    name: a.name,
+                    url: a.browser_download_url,
+                    size: a.size,
+                    file_name: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ReleaseSource for GithubSource {
+    fn releases(&self) -> Result<Vec<Release>> {
+        let releases = self.client.get_releases(&self.repo)?;
+        Ok(releases.into_iter().map(Release::from).collect())
+    }
+
+    fn release(&self, version: Option<&str>) -> Result<Release> {
+        let release = self
+            .client
+            .get_release(&self.repo, version.map(String::from))?;
+        Ok(release.into())
+    }
+}
+
+// GitLab, `project` is the full "namespace/name" path
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GitlabSource {
+    #[serde(default = "gitlab_api_url")]
+    pub api_url: String,
+    pub project: String,
+}
+
+fn gitlab_api_url() -> String {
+    GITLAB_API_URL.into()
+}
+
+#[derive(Deserialize)]
+struct GitlabRelease {
+    tag_name: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    released_at: Option<String>,
+    #[serde(default)]
+    upcoming_release: bool,
+    assets: GitlabAssets,
+}
+
+#[derive(Deserialize)]
+struct GitlabAssets {
+    links: Vec<GitlabLink>,
+}
+
+#[derive(Deserialize)]
+struct GitlabLink {
+    name: String,
+    url: String,
+    #[serde(default)]
+    direct_asset_url: Option<String>,
+}
+
+impl ReleaseSource for GitlabSource {
+    fn releases(&self) -> Result<Vec<Release>> {
+        let mut url = api_url(&self.api_url, &["projects", &self.project, "releases"])?;
+        url.query_pairs_mut().append_pair("per_page", "100");
+
+        let releases = get_pages::<GitlabRelease>(url)?;
+
+        Ok(releases
+            .into_iter()
+            .map(|r| Release {
+                name: r.name.unwrap_or_else(|| r.tag_name.clone()),
+                tag: r.tag_name,
+                published_at: r.released_at.unwrap_or_default(),
+                prerelease: r.upcoming_release,
+                draft: false,
+                body: r.description.unwrap_or_default(),
+                assets: r
+                    .assets
+                    .links
+                    .into_iter()
+                    .map(|l| Asset {
+                        name: l.name,
+                        url: l.direct_asset_url.unwrap_or(l.url),
+                        size: 0,
+                        sha256: None,
+                        file_name: None,
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+// Gitea and Forgejo, including Codeberg
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GiteaSource {
+    #[serde(default = "codeberg_api_url")]
+    pub api_url: String,
+    pub owner: String,
+    pub name: String,
+}
+
+fn codeberg_api_url() -> String {
+    CODEBERG_API_URL.into()
+}
+
+#[derive(Deserialize)]
+struct GiteaRelease {
+    tag_name: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    published_at: String,
+    #[serde(default)]
+    assets: Vec<GiteaAsset>,
+}
+
+#[derive(Deserialize)]
+struct GiteaAsset {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    browser_download_url: String,
+}
+
+impl ReleaseSource for GiteaSource {
+    fn releases(&self) -> Result<Vec<Release>> {
+        let mut url = api_url(
+            &self.api_url,
+            &["repos", &self.owner, &self.name, "releases"],
+        )?;
+        url.query_pairs_mut().append_pair("limit", "50");
+
+        let releases = get_pages::<GiteaRelease>(url)?;
+
+        Ok(releases
+            .into_iter()
+            .map(|r| Release {
+                tag: r.tag_name,
+                name: r.name,
+                published_at: r.published_at,
+                prerelease: r.prerelease,
+                draft: r.draft,
+                body: r.body,
+                assets: r
+                    .assets
+                    .into_iter()
+                    .map(|a| Asset {
+                        name: a.name,
+                        url: a.browser_download_url,
+                        size: a.size,
+                        sha256: None,
+                        file_name: None,
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+// A fixed download URL, with the version optionally scraped from a web page (or the URL
+// itself) by a regex whose capture groups are joined with '.'
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StaticSource {
+    pub url: String,
+    pub file_name: String,
+    #[serde(default)]
+    pub page: Option<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+impl StaticSource {
+    fn version(&self) -> Result<Option<String>> {
+        let Some(pattern) = &self.pattern else {
+            return Ok(None);
+        };
+
+        let regex = Regex::new(pattern).map_err(|e| eyre!("Invalid version pattern: {}", e))?;
+        let haystack = match &self.page {
+            Some(page) => client()?.get(page).send()?.error_for_status()?.text()?,
+            None => self.url.clone(),
+        };
+
+        Ok(regex.captures(&haystack).map(|caps| {
+            caps.iter()
+                .skip(1)
+                .flatten()
+                .map(|m| m.as_str())
+                .collect::<Vec<_>>()
+                .join(".")
+        }))
+    }
+}
+
+impl ReleaseSource for StaticSource {
+    fn releases(&self) -> Result<Vec<Release>> {
+        let tag = self.version()?.unwrap_or_else(|| "latest".into());
+
+        Ok(vec![Release {
+            name: tag.clone(),
+            tag,
+            assets: vec![Asset {
+                name: self.file_name.clone(),
+                url: self.url.clone(),
+                size: 0,
+                sha256: None,
+                file_name: Some(self.file_name.clone()),
+            }],
+            ..Default::default()
+        }])
+    }
+
+    // Only one version is ever on offer, so any requested version must be that one
+    fn release(&self, version: Option<&str>) -> Result<Release> {
+        let release = self.releases()?.remove(0);
+
+        match version {
+            Some(version) if version != release.tag => Err(eyre!(
+                "Release {} not available, only {} can be downloaded.",
+                version,
+                release.tag
+            )),
+            _ => Ok(release),
+        }
+    }
+}
+
+fn client() -> Result<Client> {
+    Ok(Client::builder()
+        .user_agent("obs-install-manager/1.0")
+        .build()?)
+}
+
+fn api_url(base: &str, segments: &[&str]) -> Result<Url> {
+    let mut url = Url::parse(base).map_err(|e| eyre!("Invalid API URL '{}': {}", base, e))?;
+    url.path_segments_mut()
+        .map_err(|_| eyre!("API URL cannot be a base: {}", base))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+// Follows Link rel="next" headers, which both GitLab and Gitea send
+fn get_pages<T: DeserializeOwned>(url: Url) -> Result<Vec<T>> {
+    let client = client()?;
+    let mut items = Vec::new();
+    let mut next = Some(url.to_string());
+
+    for _ in 0..git::MAX_PAGES {
+        let Some(url) = next.take() else { break };
+        let response = client.get(&url).send()?;
+
+        match response.status() {
+            StatusCode::OK => (),
+            StatusCode::NOT_FOUND => return Err(eyre!("(404) Repository not found.")),
+            status => return Err(eyre!("HTTP {}: {}", status, response.text()?)),
+        }
+
+        next = git::next_link(response.headers());
+        items.extend(
+            response
+                .json::<Vec<T>>()
+                .map_err(|e| eyre!("JSON decode error: {}", e))?,
+        );
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Laid out like the notes of an OBS Studio release, with checksums at the end
+    const BODY: &str = "\
+### New Features
+- Added NVIDIA Blackwell support to the NVENC encoder [RytoEX/derrod]
+
+### Bug Fixes
+- Fixed a crash when closing a projector [Warchamp7]
+
+### Signatures
+1b50d4fa56e1a2f09e7c4b8f3a1ce0d1f7c5e44a7f4b0e5d6c9a8b7f6e5d4c3b  OBS-Studio-31.0.2-Full-x64.zip.sig
+9f0e1d2c3b4a5968778695a4b3c2d1e0f9e8d7c6b5a49382716051f4e3d2c1b0  OBS-Studio-31.0.2-macOS-Apple.dmg.asc
+
+### Checksums
+```
+OBS-Studio-31.0.2-Full-Installer-x64.exe: 6a9e2f1d0c7b8a5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f10
+e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  OBS-Studio-31.0.2-Full-x64.zip
+D2A84F4B8B650937EC8F73CD8BE2C74ADD5A911BA64DF27458ED8229DA804A26 *OBS-Studio-31.0.2-macOS-Apple.dmg
+```
+";
+
+    fn release() -> Release {
+        Release {
+            tag: "31.0.2".into(),
+            body: BODY.into(),
+            ..Default::default()
+        }
+    }
+
+    fn asset(name: &str) -> Asset {
+        Asset {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sha256_is_read_from_the_checksum_lines() {
+        let release = release();
+        let cases = [
+            (
+                "OBS-Studio-31.0.2-Full-x64.zip",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                "OBS-Studio-31.0.2-macOS-Apple.dmg",
+                "d2a84f4b8b650937ec8f73cd8be2c74add5a911ba64df27458ed8229da804a26",
+            ),
+            (
+                "OBS-Studio-31.0.2-Full-Installer-x64.exe",
+                "6a9e2f1d0c7b8a5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f10",
+            ),
+        ];
+
+        for (name, sha256) in cases {
+            assert_eq!(
+                release.sha256(&asset(name)).as_deref(),
+                Some(sha256),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn sha256_needs_the_exact_name_beside_it() {
+        let release = release();
+        // Not listed, or only part of a listed name
+        for name in [
+            "OBS-Studio-31.0.2-macOS-Intel.dmg",
+            "OBS-Studio-31.0.2-Full-x64",
+            "Full-x64.zip",
+        ] {
+            assert_eq!(release.sha256(&asset(name)), None, "{}", name);
+        }
+
+        let release = Release {
+            body: "Built from e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 \
+                   as OBS-Studio-31.0.2-Full-x64.zip"
+                .into(),
+            ..release
+        };
+        assert_eq!(
+            release.sha256(&asset("OBS-Studio-31.0.2-Full-x64.zip")),
+            None
+        );
+    }
+
+    #[test]
+    fn asset_digest_comes_first() {
+        let published = Asset {
+            sha256: Some("0".repeat(64)),
+            ..asset("OBS-Studio-31.0.2-Full-x64.zip")
+        };
+        assert_eq!(release().sha256(&published), Some("0".repeat(64)));
+    }
+}
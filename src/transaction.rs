@@ -0,0 +1,301 @@
+use crate::{file, registry::Registry};
+use color_eyre::Result;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const BACKUP_TAG: &str = ".rollback-";
+
+// Whether `path` is a backup set aside by a transaction, which is left behind if the tool is
+// killed partway through an install
+pub fn is_backup(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    name.starts_with('.') && name.contains(BACKUP_TAG)
+}
+
+// Undo actions for an install in progress, run newest first if one of its steps fails
+pub struct Transaction {
+    undo: Vec<Undo>,
+}
+
+enum Undo {
+    // A path the install created
+    Remove(PathBuf),
+    // A path the install replaced or removed, kept in `backup` until the install finishes
+    Restore { path: PathBuf, backup: PathBuf },
+    // The registry as it was before the install
+    Registry(Registry),
+}
+
+impl Transaction {
+    pub fn begin() -> Result<Self> {
+        Ok(Self {
+            undo: vec![Undo::Registry(Registry::load()?)],
+        })
+    }
+
+    // Notes a path a step is about to create, unless it's already there
+    pub fn create(&mut self, path: &Path) {
+        if path.symlink_metadata().is_err() {
+            self.undo.push(Undo::Remove(path.into()));
+        }
+    }
+
+    // Notes paths a step has created
+    pub fn created<'p>(&mut self, paths: impl IntoIterator<Item = &'p PathBuf>) {
+        for path in paths {
+            self.undo.push(Undo::Remove(path.clone()));
+        }
+    }
+
+    // Moves a path a step is about to replace or remove out of the way, to put it back on undo
+    pub fn set_aside(&mut self, path: &Path) -> io::Result<()> {
+        if path.symlink_metadata().is_err() {
+            self.create(path);
+            return Ok(());
+        }
+
+        let backup = self.backup_path(path)?;
+        fs::rename(path, &backup)?;
+        self.undo.push(Undo::Restore {
+            path: path.into(),
+            backup,
+        });
+        Ok(())
+    }
+
+    // Copies the files in `to` which copying `from` over it would overwrite, like an edited
+    // scene collection
+    pub fn preserve(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        if !from.is_dir() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let dst_path = to.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                self.preserve(&entry.path(), &dst_path)?;
+            } else if dst_path.is_file() {
+                let backup = self.backup_path(&dst_path)?;
+                fs::copy(&dst_path, &backup)?;
+                self.undo.push(Undo::Restore {
+                    path: dst_path,
+                    backup,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Beside the original so it's a rename on the same drive, numbered in case the same path is
+    // set aside twice
+    fn backup_path(&self, path: &Path) -> io::Result<PathBuf> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let backup = path.with_file_name(format!(".{}{}{}", name, BACKUP_TAG, self.undo.len()));
+        file::remove(&backup)?;
+        Ok(backup)
+    }
+
+    // Deletes what was set aside. A backup which can't be deleted is only clutter, so errors are
+    // ignored.
+    pub fn commit(self) {
+        for undo in self.undo {
+            if let Undo::Restore { backup, .. } = undo {
+                let _ = file::remove(backup);
+            }
+        }
+    }
+
+    // Undoes everything, carrying on past failures so as much as possible is put back, and
+    // returns the first one
+    pub fn rollback(self) -> Result<()> {
+        let mut first_err = None;
+
+        for undo in self.undo.into_iter().rev() {
+            let result = match undo {
+                Undo::Remove(path) => file::remove(path).map_err(Into::into),
+                Undo::Restore { path, backup } => file::remove(&path)
+                    .and_then(|_| fs::rename(&backup, &path))
+                    .map_err(Into::into),
+                Undo::Registry(registry) => registry.save(),
+            };
+
+            if let Err(e) = result {
+                first_err.get_or_insert(e);
+            }
+        }
+
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Install;
+
+    // A fresh folder under the system temp folder
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "obs-install-manager-tx-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Files in `dir`, as `name: contents` sorted by name, including leftover backups
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                match fs::read_to_string(&path) {
+                    Ok(contents) => format!("{}: {}", name, contents),
+                    Err(_) => format!("{}/", name),
+                }
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    // Without the registry snapshot, which only the registry test restores
+    fn transaction() -> Transaction {
+        Transaction { undo: Vec::new() }
+    }
+
+    #[test]
+    fn rollback_puts_back_replaced_files_and_removes_new_ones() {
+        let dir = temp_dir("rollback");
+        fs::create_dir_all(dir.join("obs")).unwrap();
+        fs::write(dir.join("obs/obs.exe"), "30").unwrap();
+        fs::write(dir.join("launch.sh"), "old").unwrap();
+
+        let mut tx = transaction();
+        // A new version replacing the old folder
+        tx.set_aside(&dir.join("obs")).unwrap();
+        fs::create_dir_all(dir.join("obs")).unwrap();
+        fs::write(dir.join("obs/obs.exe"), "31").unwrap();
+        // A file written over
+        tx.set_aside(&dir.join("launch.sh")).unwrap();
+        fs::write(dir.join("launch.sh"), "new").unwrap();
+        // Files which weren't there
+        tx.create(&dir.join("plugin"));
+        fs::create_dir_all(dir.join("plugin")).unwrap();
+        tx.created(&[dir.join("plugin/plugin.dll")]);
+        fs::write(dir.join("plugin/plugin.dll"), "dll").unwrap();
+        // And one set aside which didn't exist, created by the step
+        tx.set_aside(&dir.join("shortcut")).unwrap();
+        fs::write(dir.join("shortcut"), "link").unwrap();
+
+        tx.rollback().unwrap();
+        assert_eq!(listing(&dir), ["launch.sh: old", "obs/"]);
+        assert_eq!(listing(&dir.join("obs")), ["obs.exe: 30"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rollback_restores_preserved_files() {
+        let dir = temp_dir("preserve");
+        let (from, to) = (dir.join("defaults"), dir.join("config"));
+        fs::create_dir_all(from.join("scenes")).unwrap();
+        fs::write(from.join("scenes/main.json"), "default").unwrap();
+        fs::write(from.join("global.ini"), "default").unwrap();
+        fs::create_dir_all(to.join("scenes")).unwrap();
+        fs::write(to.join("scenes/main.json"), "edited").unwrap();
+
+        let mut tx = transaction();
+        tx.preserve(&from, &to).unwrap();
+        // Only the file which would be overwritten is kept
+        assert_eq!(
+            listing(&to.join("scenes")),
+            [".main.json.rollback-0: edited", "main.json: edited"]
+        );
+        tx.created(&[to.join("global.ini")]);
+        fs::write(to.join("scenes/main.json"), "default").unwrap();
+        fs::write(to.join("global.ini"), "default").unwrap();
+
+        tx.rollback().unwrap();
+        assert_eq!(listing(&to), ["scenes/"]);
+        assert_eq!(listing(&to.join("scenes")), ["main.json: edited"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn commit_keeps_changes_and_deletes_backups() {
+        let dir = temp_dir("commit");
+        fs::write(dir.join("launch.sh"), "old").unwrap();
+        fs::write(dir.join("scene.json"), "edited").unwrap();
+
+        let mut tx = transaction();
+        tx.set_aside(&dir.join("launch.sh")).unwrap();
+        fs::write(dir.join("launch.sh"), "new").unwrap();
+        tx.preserve(&dir, &dir).unwrap();
+
+        tx.commit();
+        assert_eq!(listing(&dir), ["launch.sh: new", "scene.json: edited"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // The only test touching the registry beside the test binary
+    #[test]
+    fn failed_install_puts_back_files_and_registry() {
+        let dir = temp_dir("install");
+        let registry_path = Registry::path().unwrap();
+        let saved = fs::read(&registry_path).ok();
+
+        let before = Registry {
+            installs: vec![Install {
+                component: "obs".into(),
+                version: "30.2.3".into(),
+                files: vec![dir.join("obs")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        before.save().unwrap();
+        fs::create_dir_all(dir.join("obs")).unwrap();
+        fs::write(dir.join("obs/obs"), "30.2.3").unwrap();
+
+        // Steps of an update which fails after recording the new version
+        let mut tx = Transaction::begin().unwrap();
+        let result = (|| -> Result<()> {
+            tx.set_aside(&dir.join("obs"))?;
+            fs::create_dir_all(dir.join("obs"))?;
+            fs::write(dir.join("obs/obs"), "31.0.0")?;
+
+            let mut registry = Registry::load()?;
+            registry.record(Install {
+                component: "obs".into(),
+                version: "31.0.0".into(),
+                files: vec![dir.join("obs")],
+                ..Default::default()
+            });
+            registry.save()?;
+
+            Err(io::Error::other("download failed").into())
+        })();
+        assert!(result.is_err());
+        tx.rollback().unwrap();
+
+        assert_eq!(listing(&dir), ["obs/"]);
+        assert_eq!(listing(&dir.join("obs")), ["obs: 30.2.3"]);
+        let after = Registry::load().unwrap();
+        assert_eq!(after.installs.len(), 1);
+        assert_eq!(after.installs[0].version, "30.2.3");
+
+        match saved {
+            Some(saved) => fs::write(&registry_path, saved).unwrap(),
+            None => fs::remove_file(&registry_path).unwrap(),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
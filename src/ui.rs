@@ -1,3 +1,4 @@
+use crate::{app::Progress, registry::Install, source::Release};
 use color_eyre::eyre::Result;
 use ratatui::prelude::*;
 use ratatui::{
@@ -7,6 +8,7 @@ use ratatui::{
     text::Line,
     widgets::*,
 };
+use std::path::PathBuf;
 
 pub const HIGHLIGHT_STYLE: Style = Style::new()
     .bg(tailwind::SLATE.c800)
@@ -14,18 +16,52 @@ pub const HIGHLIGHT_STYLE: Style = Style::new()
 
 #[derive(Default)]
 pub struct ProgressBar {
-    pub title: &'static str,
-    pub ratio: f64,
+    pub progress: Progress,
 }
 
 impl ProgressBar {
-    pub fn set_ratio(&mut self, ratio: f64) {
-        self.ratio = ratio.min(1.0);
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.progress.total != 0
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.progress.ratio().min(1.0)
+    }
+
+    // e.g. "OBS-Studio-31.0.2-Windows.zip — 84.1/152.3 MB — 6.2 MB/s — 0:11 left"
+    pub fn label(&self) -> String {
+        let p = &self.progress;
+        let mut parts = vec![
+            p.name.clone(),
+            format!(
+                "{:.1}/{}",
+                p.done as f64 / unit(p.total).0,
+                format_bytes(p.total)
+            ),
+        ];
+
+        if p.speed > 0.0 {
+            let secs = (p.total.saturating_sub(p.done) as f64 / p.speed) as u64;
+            parts.push(format!("{}/s", format_bytes(p.speed as u64)));
+            parts.push(format!("{}:{:02} left", secs / 60, secs % 60));
+        }
+
+        parts.join(" — ")
+    }
+
+    pub fn width(&self, area: Rect) -> u16 {
+        // +4 to account for padding and borders
+        let width = self.label().chars().count() + 4;
+        area.width.min(width as u16)
     }
 
     pub fn render(&self, area: Rect, buf: &mut Buffer) {
         let block = Block::default()
-            .title(self.title)
+            .title(format!(" {} ", self.progress.phase))
             .title_alignment(Alignment::Center)
             .borders(Borders::ALL)
             .border_set(border::ROUNDED);
@@ -33,26 +69,58 @@ impl ProgressBar {
         let gauge = Gauge::default()
             .block(block)
             .gauge_style(Style::default().green())
-            .ratio(self.ratio);
+            .label(self.label())
+            .ratio(self.ratio());
 
         Widget::render(gauge, area, buf);
     }
 }
 
+fn unit(bytes: u64) -> (f64, &'static str) {
+    match bytes {
+        0..1_000 => (1.0, "B"),
+        1_000..1_000_000 => (1e3, "kB"),
+        1_000_000..1_000_000_000 => (1e6, "MB"),
+        _ => (1e9, "GB"),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let (div, suffix) = unit(bytes);
+    format!("{:.1} {}", bytes as f64 / div, suffix)
+}
+
 #[derive(Clone)]
 pub struct FnItem<T> {
-    pub op: fn(T) -> Result<()>,
+    pub op: fn(T, &str) -> Result<()>,
+    pub versions: Option<fn(T, &str) -> Result<()>>,
+    pub installs: Option<fn(T, &str) -> Result<()>>,
+    // Passed to `op`, `versions` and `installs`, e.g. a component id
+    pub arg: String,
     pub desc: String,
 }
 
 impl<T> FnItem<T> {
-    pub fn new(op: fn(T) -> Result<()>, desc: &str) -> Self {
+    pub fn new(op: fn(T, &str) -> Result<()>, arg: &str, desc: &str) -> Self {
         Self {
             op,
+            versions: None,
+            installs: None,
+            arg: arg.into(),
             desc: desc.into(),
         }
     }
 
+    pub fn with_versions(mut self, versions: fn(T, &str) -> Result<()>) -> Self {
+        self.versions = Some(versions);
+        self
+    }
+
+    pub fn with_installs(mut self, installs: fn(T, &str) -> Result<()>) -> Self {
+        self.installs = Some(installs);
+        self
+    }
+
     pub fn desc(&self) -> String {
         self.desc.clone()
     }
@@ -100,3 +168,233 @@ impl<'a, T> FnList<'a, T> {
         StatefulWidget::render(list, area, buf, &mut self.state);
     }
 }
+
+#[derive(Default)]
+pub struct ReleaseList<'a> {
+    pub releases: Vec<Release>,
+    // Component the releases belong to
+    pub component: Option<String>,
+    pub state: ListState,
+    pub header: Line<'a>,
+    pub footer: Line<'a>,
+}
+
+impl<'a> ReleaseList<'a> {
+    pub fn set_releases(&mut self, releases: Vec<Release>, component: String) {
+        self.releases = releases;
+        self.component = Some(component);
+        self.state.select(Some(0));
+    }
+
+    pub fn selected(&self) -> Option<&Release> {
+        self.state.selected().and_then(|i| self.releases.get(i))
+    }
+
+    pub fn desc(release: &Release) -> String {
+        // Dates are ISO 8601, keep only the YYYY-MM-DD part
+        let date = release
+            .published_at
+            .get(..10)
+            .unwrap_or(&release.published_at);
+        let flag = match (release.draft, release.prerelease) {
+            (true, _) => "draft",
+            (false, true) => "prerelease",
+            (false, false) => "",
+        };
+        format!("{:<16}{:<12}{}", release.tag, date, flag)
+    }
+
+    pub fn width(&self, area: Rect) -> u16 {
+        let width = self
+            .releases
+            .iter()
+            .map(|r| Self::desc(r).len())
+            .max()
+            .unwrap_or(0);
+        // +6 to account for padding, borders and highlight symbol
+        let width = (width + 2)
+            .max(self.header.width())
+            .max(self.footer.width())
+            + 4;
+        area.width.min(width as u16)
+    }
+
+    pub fn height(&self, area: Rect) -> u16 {
+        // +4 to account for padding and borders
+        let height = self.releases.len() + 4;
+        area.height.min(height as u16)
+    }
+
+    pub fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title_top(self.header.clone().centered())
+            .title_bottom(self.footer.clone().centered())
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .padding(Padding::uniform(1));
+
+        let items: Vec<String> = self.releases.iter().map(Self::desc).collect();
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_symbol("> ")
+            .highlight_style(HIGHLIGHT_STYLE)
+            .highlight_spacing(HighlightSpacing::Always);
+
+        StatefulWidget::render(list, area, buf, &mut self.state);
+    }
+}
+
+// Versions of a component installed side by side
+#[derive(Default)]
+pub struct InstallList<'a> {
+    pub installs: Vec<Install>,
+    // Folder of the install which gets launched
+    pub active: Option<PathBuf>,
+    // Component the installs belong to
+    pub component: Option<String>,
+    pub state: ListState,
+    pub header: Line<'a>,
+    pub footer: Line<'a>,
+}
+
+impl<'a> InstallList<'a> {
+    pub fn set_installs(
+        &mut self,
+        installs: Vec<Install>,
+        active: Option<PathBuf>,
+        component: String,
+    ) {
+        let selected = self.state.selected().unwrap_or(0);
+        let keep = self.component.as_ref() == Some(&component);
+        self.installs = installs;
+        self.active = active;
+        self.component = Some(component);
+        // Stay on the same row when refreshed after switching
+        self.state.select(Some(match keep {
+            true => selected.min(self.installs.len().saturating_sub(1)),
+            false => 0,
+        }));
+    }
+
+    pub fn selected(&self) -> Option<&Install> {
+        self.state.selected().and_then(|i| self.installs.get(i))
+    }
+
+    pub fn is_active(&self, install: &Install) -> bool {
+        self.active.is_some() && install.path == self.active
+    }
+
+    pub fn desc(&self, install: &Install) -> String {
+        let folder = install
+            .path
+            .as_ref()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let flag = match self.is_active(install) {
+            true => "active",
+            false => "",
+        };
+        format!("{:<16}{:<40}{}", install.version, folder, flag)
+    }
+
+    pub fn width(&self, area: Rect) -> u16 {
+        let width = self
+            .installs
+            .iter()
+            .map(|i| self.desc(i).trim_end().chars().count())
+            .max()
+            .unwrap_or(0);
+        // +6 to account for padding, borders and highlight symbol
+        let width = (width + 2)
+            .max(self.header.width())
+            .max(self.footer.width())
+            + 4;
+        area.width.min(width as u16)
+    }
+
+    pub fn height(&self, area: Rect) -> u16 {
+        // +4 to account for padding and borders
+        let height = self.installs.len().max(1) + 4;
+        area.height.min(height as u16)
+    }
+
+    pub fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title_top(self.header.clone().centered())
+            .title_bottom(self.footer.clone().centered())
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .padding(Padding::uniform(1));
+
+        let items: Vec<String> = match self.installs.is_empty() {
+            true => vec!["Nothing installed yet".into()],
+            false => self.installs.iter().map(|i| self.desc(i)).collect(),
+        };
+
+        let list = List::new(items)
+            .block(block)
+            .highlight_symbol("> ")
+            .highlight_style(HIGHLIGHT_STYLE)
+            .highlight_spacing(HighlightSpacing::Always);
+
+        StatefulWidget::render(list, area, buf, &mut self.state);
+    }
+}
+
+// A message awaiting confirmation, e.g. an install's plan or before uninstalling
+#[derive(Default)]
+pub struct Confirm<'a> {
+    pub lines: Vec<String>,
+    // Lines scrolled past, for messages taller than the screen
+    pub scroll: u16,
+    pub header: Line<'a>,
+    pub footer: Line<'a>,
+}
+
+impl<'a> Confirm<'a> {
+    pub fn scroll_up(&mut self) {
+        self.scroll = self.scroll.saturating_sub(1);
+    }
+
+    pub fn scroll_down(&mut self) {
+        let last = self.lines.len().saturating_sub(1) as u16;
+        self.scroll = (self.scroll + 1).min(last);
+    }
+
+    pub fn width(&self, area: Rect) -> u16 {
+        let width = self
+            .lines
+            .iter()
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or(0);
+        // +4 to account for padding and borders
+        let width = width.max(self.header.width()).max(self.footer.width()) + 4;
+        area.width.min(width as u16)
+    }
+
+    pub fn height(&self, area: Rect) -> u16 {
+        // +4 to account for padding and borders
+        let height = self.lines.len() + 4;
+        area.height.min(height as u16)
+    }
+
+    pub fn render(&self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title_top(self.header.clone().centered())
+            .title_bottom(self.footer.clone().centered())
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_set(border::ROUNDED)
+            .padding(Padding::uniform(1));
+
+        let text: Vec<Line> = self.lines.iter().map(|l| Line::from(l.as_str())).collect();
+        let paragraph = Paragraph::new(text).block(block).scroll((self.scroll, 0));
+
+        Widget::render(paragraph, area, buf);
+    }
+}
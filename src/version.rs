@@ -0,0 +1,365 @@
+use color_eyre::{Result, eyre::eyre};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+// A release version such as `31.0.0`, `31.1.0-beta2` or `v0.4.1`. Ordering follows semantic
+// versioning, except that numeric runs inside pre-release identifiers compare as numbers, so
+// `beta10` sorts after `beta2`.
+#[derive(Clone, Debug)]
+pub struct Version {
+    pub release: Vec<u64>,
+    pub pre: Vec<Ident>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ident {
+    Num(u64),
+    Alpha(String),
+}
+
+impl Version {
+    pub fn major(&self) -> u64 {
+        self.part(0)
+    }
+
+    pub fn minor(&self) -> u64 {
+        self.part(1)
+    }
+
+    pub fn patch(&self) -> u64 {
+        self.part(2)
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    // Missing trailing parts count as zero, so `30` == `30.0.0`
+    fn part(&self, i: usize) -> u64 {
+        self.release.get(i).copied().unwrap_or(0)
+    }
+
+    fn cmp_release(&self, other: &Self) -> Ordering {
+        let len = self.release.len().max(other.release.len());
+        (0..len)
+            .map(|i| self.part(i).cmp(&other.part(i)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn starts_with(&self, prefix: &[u64]) -> bool {
+        prefix.iter().enumerate().all(|(i, p)| self.part(i) == *p)
+    }
+}
+
+impl FromStr for Version {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tag = s.trim();
+        let tag = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+
+        // Build metadata never affects precedence
+        let tag = tag.split_once('+').map_or(tag, |(t, _)| t);
+        let (release, pre) = tag.split_once('-').unwrap_or((tag, ""));
+
+        let release = release
+            .split('.')
+            .map(|p| p.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| eyre!("Invalid version: {}", s))?;
+
+        let pre = if pre.is_empty() {
+            Vec::new()
+        } else {
+            pre.split(['.', '-']).flat_map(split_ident).collect()
+        };
+
+        Ok(Self { release, pre })
+    }
+}
+
+// Splits `beta2` into `beta` and `2`
+fn split_ident(ident: &str) -> Vec<Ident> {
+    let mut idents = Vec::new();
+    let mut rest = ident;
+
+    while let Some(c) = rest.chars().next() {
+        let is_digit = c.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+
+        idents.push(match run.parse::<u64>() {
+            Ok(n) if is_digit => Ident::Num(n),
+            _ => Ident::Alpha(run.to_lowercase()),
+        });
+        rest = tail;
+    }
+
+    idents
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_release(other).then_with(|| {
+            // A pre-release sorts before the release it leads up to
+            match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            }
+        })
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let release: Vec<String> = self.release.iter().map(|p| p.to_string()).collect();
+        write!(f, "{}", release.join("."))?;
+
+        if !self.pre.is_empty() {
+            write!(f, "-")?;
+            for (i, ident) in self.pre.iter().enumerate() {
+                // Runs of the same kind can only have come from separate identifiers
+                let prev = i.checked_sub(1).map(|i| &self.pre[i]);
+                let same_kind = matches!(
+                    (prev, ident),
+                    (Some(Ident::Num(_)), Ident::Num(_)) | (Some(Ident::Alpha(_)), Ident::Alpha(_))
+                );
+                if same_kind {
+                    write!(f, ".")?;
+                }
+
+                match ident {
+                    Ident::Num(n) => write!(f, "{}", n)?,
+                    Ident::Alpha(a) => write!(f, "{}", a)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A comma separated list of comparators which must all match, e.g. `>=30, <32`, `~30.1`,
+// `^0.4`, `=31.0.2` or `30.x`. A bare partial version such as `30` matches any `30.*`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionReq {
+    pub comparators: Vec<Comparator>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Comparator {
+    Exact(Version),
+    Greater(Version),
+    GreaterEq(Version),
+    Less(Version),
+    LessEq(Version),
+    Prefix(Vec<u64>),
+}
+
+impl VersionReq {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+impl Comparator {
+    pub fn matches(&self, version: &Version) -> bool {
+        match self {
+            Self::Exact(v) => version == v,
+            Self::Greater(v) => version > v,
+            Self::GreaterEq(v) => version >= v,
+            // As in semver, `<32` doesn't let in `32.0.0-beta1` on its way to `32.0.0`
+            Self::Less(v) => {
+                version < v
+                    && !(version.is_prerelease()
+                        && !v.is_prerelease()
+                        && version.cmp_release(v).is_eq())
+            }
+            Self::LessEq(v) => version <= v,
+            // Nor does `30` let in `30.0.0-beta1`, which comes before `30.0.0`
+            Self::Prefix(p) => {
+                let lower = Version {
+                    release: p.clone(),
+                    pre: Vec::new(),
+                };
+                version.starts_with(p) && *version >= lower
+            }
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut comparators = Vec::new();
+
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            comparators.extend(parse_comparator(part).map_err(|e| eyre!("{} in '{}'", e, s))?);
+        }
+
+        Ok(Self { comparators })
+    }
+}
+
+fn parse_comparator(s: &str) -> Result<Vec<Comparator>> {
+    let ops = [">=", "<=", ">", "<", "=", "~", "^"];
+    let op = ops
+        .iter()
+        .find(|op| s.starts_with(**op))
+        .copied()
+        .unwrap_or("");
+    let rest = s[op.len()..].trim();
+
+    if rest == "*" {
+        return Ok(Vec::new());
+    }
+
+    // `30.x` and `30.*` are prefixes, like a bare `30`
+    let trimmed = rest.trim_end_matches(".x").trim_end_matches(".*");
+    let version = trimmed.parse::<Version>()?;
+    let prefix = version.release.clone();
+    let is_partial = prefix.len() < 3 || trimmed.len() != rest.len();
+
+    let upper = |i: usize| {
+        let mut release = prefix[..=i.min(prefix.len() - 1)].to_vec();
+        *release.last_mut().unwrap() += 1;
+        Version {
+            release,
+            pre: Vec::new(),
+        }
+    };
+
+    Ok(match op {
+        ">=" => vec![Comparator::GreaterEq(version)],
+        "<=" => vec![Comparator::LessEq(version)],
+        ">" => vec![Comparator::Greater(version)],
+        "<" => vec![Comparator::Less(version)],
+        "=" if is_partial => vec![Comparator::Prefix(prefix)],
+        "=" => vec![Comparator::Exact(version)],
+        // ~1.2.3 := >=1.2.3, <1.3.0 and ~1 := >=1.0.0, <2.0.0
+        "~" => vec![
+            Comparator::Less(upper(if prefix.len() > 1 { 1 } else { 0 })),
+            Comparator::GreaterEq(version),
+        ],
+        // ^1.2.3 := >=1.2.3, <2.0.0 and ^0.4.1 := >=0.4.1, <0.5.0
+        "^" => {
+            let i = prefix
+                .iter()
+                .position(|p| *p != 0)
+                .unwrap_or(prefix.len() - 1);
+            vec![Comparator::Less(upper(i)), Comparator::GreaterEq(version)]
+        }
+        _ if is_partial => vec![Comparator::Prefix(prefix)],
+        _ => vec![Comparator::Exact(version)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_tags() {
+        let version = v("v31.1.0-beta2+build.5");
+        assert_eq!(version.release, [31, 1, 0]);
+        assert_eq!(version.pre, [Ident::Alpha("beta".into()), Ident::Num(2)]);
+        assert_eq!(version.to_string(), "31.1.0-beta2");
+        assert_eq!(v("0.4").to_string(), "0.4");
+        // Only identifiers of the same kind need a separating dot
+        assert_eq!(v("1.0.0-rc.1").to_string(), "1.0.0-rc1");
+        assert_eq!(v("1.0.0-1.2").to_string(), "1.0.0-1.2");
+        assert!("31.x.0".parse::<Version>().is_err());
+        assert!("latest".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn orders_like_semver() {
+        let sorted = [
+            "29.1.3",
+            "30.0.0-beta1",
+            "30.0.0-beta2",
+            "30.0.0-beta10",
+            "30.0.0-rc1",
+            "30.0.0",
+            "30.0.1",
+            "30.1",
+            "31.0.0",
+        ];
+        for pair in sorted.windows(2) {
+            assert!(v(pair[0]) < v(pair[1]), "{} < {}", pair[0], pair[1]);
+        }
+        assert_eq!(v("30"), v("30.0.0"));
+        assert_eq!(v("v30.0.0"), v("30.0.0+abc"));
+    }
+
+    #[test]
+    fn ranges_exclude_prereleases_of_their_upper_bound() {
+        let range = req(">=30, <32");
+        assert!(range.matches(&v("30.0.0")));
+        assert!(range.matches(&v("31.2.1")));
+        assert!(range.matches(&v("31.0.0-beta1")));
+        assert!(!range.matches(&v("30.0.0-beta1")));
+        assert!(!range.matches(&v("32.0.0-beta1")));
+        assert!(!range.matches(&v("32.0.0")));
+
+        assert!(!req("^30.1").matches(&v("31.0.0-rc1")));
+        assert!(!req("~30.1").matches(&v("30.2.0-beta1")));
+        assert!(req("<32.0.0-rc1").matches(&v("32.0.0-beta1")));
+    }
+
+    #[test]
+    fn partial_versions_match_as_prefixes() {
+        for s in ["30", "30.x", "=30", "30.*"] {
+            let prefix = req(s);
+            assert!(prefix.matches(&v("30.0.0")), "{}", s);
+            assert!(prefix.matches(&v("30.2.1")), "{}", s);
+            assert!(!prefix.matches(&v("30.0.0-beta1")), "{}", s);
+            assert!(!prefix.matches(&v("31.0.0")), "{}", s);
+        }
+        assert!(req("30.1").matches(&v("30.1.0")));
+        assert!(!req("30.1").matches(&v("30.1.0-rc1")));
+        assert!(req("30.1").matches(&v("30.1.2-rc1")));
+    }
+
+    #[test]
+    fn parses_requirements() {
+        assert_eq!(req("=31.0.2").comparators, [Comparator::Exact(v("31.0.2"))]);
+        assert_eq!(
+            req("^0.4.1").comparators,
+            [
+                Comparator::Less(v("0.5")),
+                Comparator::GreaterEq(v("0.4.1"))
+            ]
+        );
+        assert!(req("*").comparators.is_empty());
+        assert!(req("").matches(&v("1.0.0-alpha")));
+        assert!(">=thirty".parse::<VersionReq>().is_err());
+    }
+}